@@ -0,0 +1,181 @@
+//! Local stand-in for the Idealista RapidAPI. Serves responses recorded with
+//! `IDEALISTA_RECORD_DIR` (same file layout as replay mode) over HTTP, with
+//! optional error injection, so the live request path can be exercised offline.
+//!
+//! ```sh
+//! cargo run --bin mock_idealista -- --port 8080 --fixtures data/fixtures/idealista --error-every 5
+//! ```
+//!
+//! Then set `api.base_url = "http://127.0.0.1:8080"` in the scrape config.
+
+use housevaluation::core::scrapers::band::SearchBand;
+use housevaluation::core::scrapers::replay;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use tiny_http::{Header, Request, Response, Server};
+
+struct Options {
+    port: u16,
+    fixtures: PathBuf,
+    /// Answer every Nth request with a 503.
+    error_every: Option<u64>,
+    /// Answer every Nth request with a 429 and `Retry-After: 1`.
+    throttle_every: Option<u64>,
+    /// Requests allowed before every response becomes a 429, reported in the RapidAPI quota headers.
+    quota: Option<u64>,
+    /// Reject requests whose `x-rapidapi-key` doesn't match.
+    api_key: Option<String>,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, Box<dyn Error>> {
+        let mut options = Options {
+            port: 8080,
+            fixtures: PathBuf::from("data/fixtures/idealista"),
+            error_every: None,
+            throttle_every: None,
+            quota: None,
+            api_key: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", flag));
+            match flag.as_str() {
+                "--port" => options.port = value()?.parse()?,
+                "--fixtures" => options.fixtures = PathBuf::from(value()?),
+                "--error-every" => options.error_every = Some(value()?.parse()?),
+                "--throttle-every" => options.throttle_every = Some(value()?.parse()?),
+                "--quota" => options.quota = Some(value()?.parse()?),
+                "--api-key" => options.api_key = Some(value()?),
+                _ => return Err(format!("Unknown flag {}", flag).into()),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args()?;
+    let server = Server::http(("127.0.0.1", options.port))
+        .map_err(|e| format!("Couldn't bind port {}: {}", options.port, e))?;
+
+    println!("Mock Idealista API listening on http://127.0.0.1:{} serving {}", options.port, options.fixtures.display());
+
+    let mut request_count: u64 = 0;
+    for request in server.incoming_requests() {
+        request_count += 1;
+        let (status, body, extra_headers) = handle(&options, &request, request_count);
+        println!("{} {} -> {}", request.method(), request.url(), status);
+
+        let mut response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json"));
+        for (name, value) in extra_headers {
+            response = response.with_header(header(name, &value));
+        }
+
+        if let Err(e) = request.respond(response) {
+            println!("Failed to respond: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(options: &Options, request: &Request, request_count: u64) -> (u16, String, Vec<(&'static str, String)>) {
+    let mut headers = Vec::new();
+
+    if let Some(quota) = options.quota {
+        headers.push(("x-ratelimit-requests-limit", quota.to_string()));
+        headers.push(("x-ratelimit-requests-remaining", quota.saturating_sub(request_count).to_string()));
+        if request_count > quota {
+            return (429, error_body("Quota exceeded"), headers);
+        }
+    }
+
+    if let Some(api_key) = &options.api_key {
+        let sent = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("x-rapidapi-key"))
+            .map(|h| h.value.as_str());
+        if sent != Some(api_key.as_str()) {
+            return (403, error_body("Invalid API key"), headers);
+        }
+    }
+
+    if options.throttle_every.is_some_and(|n| n > 0 && request_count.is_multiple_of(n)) {
+        headers.push(("Retry-After", "1".to_string()));
+        return (429, error_body("Too many requests"), headers);
+    }
+    if options.error_every.is_some_and(|n| n > 0 && request_count.is_multiple_of(n)) {
+        return (503, error_body("Injected failure"), headers);
+    }
+
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let params = query_params(query);
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+
+    let key = match path.trim_end_matches('/') {
+        "/getsuggestions" => replay::suggestions_key(param("location"), param("operation"), param("prefix")),
+        "/listhomes" => {
+            let num_page = param("numPage").parse().unwrap_or(1);
+            let band = SearchBand::from_params(|name| params.get(name).and_then(|v| v.parse().ok()));
+            replay::listings_key(param("operation"), param("propertyType"), param("locationId"), &band.key(), num_page)
+        }
+        _ => return (404, error_body("Unknown endpoint"), headers),
+    };
+
+    match fs::read_to_string(replay::response_path(&options.fixtures, &key)) {
+        Ok(body) => (200, body, headers),
+        Err(_) => (404, error_body(&format!("No fixture for {}", key)), headers),
+    }
+}
+
+fn query_params(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (percent_decode(name), percent_decode(value)))
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match u8::from_str_radix(&value[i + 1..i + 3], 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "message": message }).to_string()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
@@ -0,0 +1,242 @@
+use ndarray::{Array1, ArrayView1, ArrayView2};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/// Hyperparameters of a gradient-boosted tree model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoostingParams {
+    pub trees: usize,
+    /// Shrinks each tree's contribution; smaller needs more trees but overfits less.
+    pub learning_rate: f64,
+    pub max_depth: usize,
+    /// A split is only made if both sides keep at least this many listings.
+    pub min_samples_leaf: usize,
+}
+
+impl BoostingParams {
+    /// Candidates tried by cross-validation.
+    pub fn grid() -> Vec<Self> {
+        let mut grid = Vec::new();
+        for &trees in &[100, 300] {
+            for &learning_rate in &[0.05, 0.1] {
+                for &max_depth in &[3, 5] {
+                    for &min_samples_leaf in &[5, 10] {
+                        grid.push(Self { trees, learning_rate, max_depth, min_samples_leaf });
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// Fits `trees` regression trees, each on the residuals of the ones before it.
+    pub fn fit(&self, records: ArrayView2<f64>, targets: ArrayView1<f64>) -> Result<GradientBoosting, Box<dyn Error>> {
+        if targets.is_empty() {
+            return Err("No listings to fit the gradient-boosted trees on".into());
+        }
+        if self.trees == 0 || self.max_depth == 0 || self.min_samples_leaf == 0 || self.learning_rate <= 0.0 {
+            return Err(format!("Invalid gradient boosting parameters: {}", self).into());
+        }
+
+        let base = targets.mean().unwrap_or(0.0);
+        let mut predictions = vec![base; targets.len()];
+        let mut trees = Vec::with_capacity(self.trees);
+
+        for _ in 0..self.trees {
+            let residuals: Vec<f64> = targets.iter().zip(&predictions).map(|(y, p)| y - p).collect();
+            let tree = Tree::fit(records, &residuals, self);
+            for (i, prediction) in predictions.iter_mut().enumerate() {
+                *prediction += self.learning_rate * tree.predict(records.row(i));
+            }
+            trees.push(tree);
+        }
+
+        Ok(GradientBoosting { base, learning_rate: self.learning_rate, trees })
+    }
+}
+
+impl fmt::Display for BoostingParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trees: {}, learning_rate: {}, max_depth: {}, min_samples_leaf: {}",
+            self.trees, self.learning_rate, self.max_depth, self.min_samples_leaf
+        )
+    }
+}
+
+/// Gradient-boosted regression trees with squared loss. Unlike a linear model, a tree can
+/// split on one feature and then another, so it picks up interactions such as a pool
+/// mattering more in the suburbs than downtown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradientBoosting {
+    /// Mean target, the prediction before any tree.
+    base: f64,
+    learning_rate: f64,
+    trees: Vec<Tree>,
+}
+
+impl GradientBoosting {
+    pub fn predict(&self, records: ArrayView2<f64>) -> Array1<f64> {
+        records
+            .rows()
+            .into_iter()
+            .map(|row| self.base + self.learning_rate * self.trees.iter().map(|tree| tree.predict(row)).sum::<f64>())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Node {
+    Leaf { value: f64 },
+    /// Rows with `feature <= threshold` go left. Children are indices into `Tree::nodes`.
+    Split { feature: usize, threshold: f64, left: usize, right: usize },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tree {
+    /// The root is the first node.
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn fit(records: ArrayView2<f64>, residuals: &[f64], params: &BoostingParams) -> Self {
+        let mut tree = Self { nodes: Vec::new() };
+        tree.grow(records, residuals, (0..residuals.len()).collect(), 0, params);
+        tree
+    }
+
+    /// Adds the subtree for the rows in `indices` and returns its index.
+    fn grow(
+        &mut self,
+        records: ArrayView2<f64>,
+        residuals: &[f64],
+        indices: Vec<usize>,
+        depth: usize,
+        params: &BoostingParams,
+    ) -> usize {
+        let id = self.nodes.len();
+        let mean = indices.iter().map(|&i| residuals[i]).sum::<f64>() / indices.len() as f64;
+        self.nodes.push(Node::Leaf { value: mean });
+
+        if depth >= params.max_depth || indices.len() < 2 * params.min_samples_leaf {
+            return id;
+        }
+        let Some((feature, threshold)) = best_split(records, residuals, &indices, params.min_samples_leaf) else {
+            return id;
+        };
+
+        let (left, right): (Vec<usize>, Vec<usize>) =
+            indices.into_iter().partition(|&i| records[[i, feature]] <= threshold);
+        let left = self.grow(records, residuals, left, depth + 1, params);
+        let right = self.grow(records, residuals, right, depth + 1, params);
+        self.nodes[id] = Node::Split { feature, threshold, left, right };
+        id
+    }
+
+    fn predict(&self, row: ArrayView1<f64>) -> f64 {
+        let mut node = 0;
+        loop {
+            match self.nodes[node] {
+                Node::Leaf { value } => return value,
+                Node::Split { feature, threshold, left, right } => {
+                    node = if row[feature] <= threshold { left } else { right };
+                }
+            }
+        }
+    }
+}
+
+/// Feature and threshold that most reduce the squared error of the residuals, if any
+/// split keeps `min_samples_leaf` rows on each side.
+fn best_split(records: ArrayView2<f64>, residuals: &[f64], indices: &[usize], min_samples_leaf: usize) -> Option<(usize, f64)> {
+    let n = indices.len();
+    let total: f64 = indices.iter().map(|&i| residuals[i]).sum();
+    let parent_score = total * total / n as f64;
+
+    let mut best: Option<(usize, f64)> = None;
+    let mut best_gain = 1e-12;
+    let mut sorted = indices.to_vec();
+
+    for feature in 0..records.ncols() {
+        sorted.sort_by(|&a, &b| records[[a, feature]].total_cmp(&records[[b, feature]]));
+
+        let mut left_sum = 0.0;
+        for (k, &i) in sorted.iter().enumerate().take(n - min_samples_leaf) {
+            left_sum += residuals[i];
+            let left_count = k + 1;
+            let value = records[[i, feature]];
+            let next = records[[sorted[k + 1], feature]];
+            if left_count < min_samples_leaf || value == next {
+                continue;
+            }
+
+            let right_sum = total - left_sum;
+            let right_count = n - left_count;
+            let gain = left_sum * left_sum / left_count as f64 + right_sum * right_sum / right_count as f64 - parent_score;
+            if gain > best_gain {
+                best_gain = gain;
+                best = Some((feature, (value + next) / 2.0));
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    fn params(trees: usize, learning_rate: f64, max_depth: usize) -> BoostingParams {
+        BoostingParams { trees, learning_rate, max_depth, min_samples_leaf: 1 }
+    }
+
+    fn mse(model: &GradientBoosting, records: &Array2<f64>, targets: &Array1<f64>) -> f64 {
+        let predicted = model.predict(records.view());
+        (&predicted - targets).mapv(|e| e * e).mean().unwrap()
+    }
+
+    #[test]
+    fn a_single_split_recovers_a_step_function() {
+        let records = Array2::from_shape_fn((10, 1), |(i, _)| i as f64);
+        let targets = Array1::from_shape_fn(10, |i| if i < 5 { 100.0 } else { 300.0 });
+
+        let model = params(1, 1.0, 1).fit(records.view(), targets.view()).unwrap();
+
+        assert_eq!(model.trees[0].nodes.len(), 3);
+        match model.trees[0].nodes[0] {
+            Node::Split { feature, threshold, .. } => assert_eq!((feature, threshold), (0, 4.5)),
+            Node::Leaf { .. } => panic!("expected a split"),
+        }
+        assert_eq!(model.predict(records.view()), targets);
+    }
+
+    #[test]
+    fn training_loss_does_not_increase_with_more_trees() {
+        let records = Array2::from_shape_fn((40, 2), |(i, j)| ((i * (j + 3)) % 17) as f64);
+        let targets = Array1::from_shape_fn(40, |i| ((i * 7) % 11) as f64 * 1_000.0 + (i % 3) as f64 * 250.0);
+
+        let mut previous = f64::INFINITY;
+        for trees in 1..=10 {
+            let model = params(trees, 0.3, 2).fit(records.view(), targets.view()).unwrap();
+            let loss = mse(&model, &records, &targets);
+            assert!(loss <= previous + 1e-9, "{} trees: {} > {}", trees, loss, previous);
+            previous = loss;
+        }
+    }
+
+    #[test]
+    fn a_constant_target_is_never_split() {
+        let records = Array2::from_shape_fn((20, 3), |(i, j)| (i + j) as f64);
+        let targets = Array1::from_elem(20, 250_000.0);
+
+        let model = params(5, 0.1, 3).fit(records.view(), targets.view()).unwrap();
+
+        assert!(model.trees.iter().all(|tree| tree.nodes.len() == 1));
+        assert!(model.predict(records.view()).iter().all(|&p| p == 250_000.0));
+    }
+}
@@ -1,187 +1,588 @@
 use linfa::prelude::*;
 
-use csv::Reader;
 use linfa::Dataset;
 use linfa_elasticnet::ElasticNet;
-use ndarray::{arr0, Array0, Array1, Array2, ArrayView1};
+use ndarray::{Array1, Array2};
 
 use serde::{Deserialize, Serialize};
 
 use std::error::Error;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::fmt;
 use std::time::Instant;
-use crate::core::types::Property;
-
-const MODEL_FILE: &str = "output/cervo_model.bin";
+use crate::core::boosting::{BoostingParams, GradientBoosting};
+use crate::core::encoding::{self, LocationEncoder};
+use crate::core::features::{self, Feature};
+use crate::core::imputation::{self, ImputationReport, Imputer};
+use crate::core::metrics::Metric;
+use crate::core::preprocessing::{self, Pipeline, Scaling, Target, TargetTransform};
+use crate::core::storage::{ModelRun, Storage};
+use crate::core::types::{Country, Operation, Property, PropertyType};
+
+type TrainingSet = Dataset<f64, f64, ndarray::Ix1>;
+type PreparedData = (TrainingData, Pipeline, ImputationReport);
 
 #[derive(Serialize, Deserialize)]
 pub struct Cervo {
-    model: ElasticNet<f64>,
+    model: Model,
+    /// Builds the input columns the model was trained on, filling missing values the same way.
+    imputer: Imputer,
+    /// Log transforms and scaling applied to the imputed rows.
+    pipeline: Pipeline,
+    /// Converts the model's output back to euros.
+    target: TargetTransform,
+    selection: Selection,
 }
 
-impl Cervo {
-    pub fn new(filename: &str) -> Result<Self, Box<dyn Error>> {
-        if let Ok(model) = Self::load_model() {
-            return Ok(Self { model });
+/// Which regressor is trained, from `MODEL_KIND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    ElasticNet,
+    GradientBoosting,
+}
+
+impl ModelKind {
+    /// Reads `MODEL_KIND` (`elastic_net` or `gradient_boosting`), defaulting to elastic net.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("MODEL_KIND").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "elastic_net" => Ok(ModelKind::ElasticNet),
+            "gradient_boosting" => Ok(ModelKind::GradientBoosting),
+            other => Err(format!("Unknown MODEL_KIND {}, expected elastic_net or gradient_boosting", other).into()),
+        }
+    }
+
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            ModelKind::ElasticNet => "elastic_net",
+            ModelKind::GradientBoosting => "gradient_boosting",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Model {
+    ElasticNet(ElasticNet<f64>),
+    GradientBoosting(GradientBoosting),
+}
+
+impl Model {
+    fn predict(&self, records: &Array2<f64>) -> Array1<f64> {
+        match self {
+            Model::ElasticNet(model) => model.predict(records),
+            Model::GradientBoosting(model) => model.predict(records.view()),
         }
+    }
+}
 
-        let mut dataset = Self::load_data(filename)?;
-        let model = Self::train_model(&mut dataset)?;
-        Self::save_model(&model)?;
+/// A candidate of the hyperparameter search.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hyperparameters {
+    ElasticNet { penalty: f64, l1_ratio: f64 },
+    GradientBoosting(BoostingParams),
+}
 
-        Ok(Self { model })
+impl Hyperparameters {
+    /// Candidates tried by cross-validation for `kind`.
+    fn grid(kind: ModelKind) -> Vec<Self> {
+        match kind {
+            ModelKind::ElasticNet => {
+                let penalties = [0.01, 0.05, 0.1, 0.2, 0.3, 0.5, 1.0, 1.1, 1.2, 1.5, 2.0];
+                let l1_ratios = [0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+                penalties
+                    .iter()
+                    .flat_map(|&penalty| l1_ratios.iter().map(move |&l1_ratio| Hyperparameters::ElasticNet { penalty, l1_ratio }))
+                    .collect()
+            }
+            ModelKind::GradientBoosting => BoostingParams::grid().into_iter().map(Hyperparameters::GradientBoosting).collect(),
+        }
     }
 
-    fn load_data(filename: &str) -> Result<Dataset<f64, f64, ndarray::Ix1>, Box<dyn Error>> {
-        let mut rdr = Reader::from_path(filename)?;
-        let mut x_data = Vec::new();
-        let mut y_data = Vec::new();
+    pub fn kind(&self) -> ModelKind {
+        match self {
+            Hyperparameters::ElasticNet { .. } => ModelKind::ElasticNet,
+            Hyperparameters::GradientBoosting(_) => ModelKind::GradientBoosting,
+        }
+    }
 
-        for result in rdr.records() {
-            let record = result?;
-            let target: f64 = record[1].parse()?;
-            let size: f64 = record[2].parse()?;
-            let floor: f64 = record[3].parse().unwrap_or(0.0);
-            let latitude: f64 = record[9].parse()?;
-            let longitude: f64 = record[10].parse()?;
-            let has_lift: f64 = if record[11].trim() == "true" { 1.0 } else { 0.0 };
-            let price_by_area: f64 = record[12].parse()?;
-            let rooms: f64 = record[13].parse()?;
-            let bathrooms: f64 = record[14].parse()?;
-            let swimming_pool: f64 = if record[15].trim() == "true" { 1.0 } else { 0.0 };
-            let garden: f64 = if record[16].trim() == "true" { 1.0 } else { 0.0 };
-            let garage: f64 = if record[17].trim() == "true" { 1.0 } else { 0.0 };
+    fn fit(&self, dataset: &TrainingSet) -> Result<Model, Box<dyn Error>> {
+        match self {
+            Hyperparameters::ElasticNet { penalty, l1_ratio } => {
+                let params = ElasticNet::params().penalty(*penalty).l1_ratio(*l1_ratio);
+                Ok(Model::ElasticNet(params.fit(dataset)?))
+            }
+            Hyperparameters::GradientBoosting(params) => {
+                Ok(Model::GradientBoosting(params.fit(dataset.records().view(), dataset.targets().view())?))
+            }
+        }
+    }
+}
 
-            x_data.push(vec![
-                size, floor, latitude, longitude, has_lift, price_by_area, rooms, bathrooms,
-                swimming_pool, garden, garage
-            ]);
-            y_data.push(target);
+impl fmt::Display for Hyperparameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hyperparameters::ElasticNet { penalty, l1_ratio } => write!(f, "penalty: {} and l1_ratio: {}", penalty, l1_ratio),
+            Hyperparameters::GradientBoosting(params) => write!(f, "{}", params),
         }
+    }
+}
 
+/// How cross-validation assigns listings to folds. Models saved before it was recorded
+/// held out whole cities per fold and fail to load, so they're retrained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoldAssignment {
+    /// Listing `i` goes to fold `i % k`, so every fold mixes all locations. The imputer,
+    /// price prior and location encoder are refitted on each fold's training listings.
+    Interleaved,
+}
+
+impl FoldAssignment {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FoldAssignment::Interleaved => "interleaved",
+        }
+    }
+}
+
+/// Hyperparameters picked by cross-validation and the score they reached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Selection {
+    pub metric: Metric,
+    /// Mean score over the folds.
+    pub score: f64,
+    pub hyperparameters: Hyperparameters,
+    pub k_folds: usize,
+    pub folds: FoldAssignment,
+}
+
+struct SelectedModel {
+    model: Model,
+    target: TargetTransform,
+    selection: Selection,
+}
+
+/// Training rows with the target in model space, plus the prices and sizes needed to
+/// score predictions in euros.
+struct TrainingData {
+    dataset: TrainingSet,
+    prices: Vec<f64>,
+    sizes: Vec<f64>,
+}
+
+/// A cross-validation fold, prepared by an imputer and pipeline fitted on its training
+/// listings only, so the held-out prices never reach the features they're scored on.
+struct Fold {
+    train: TrainingData,
+    valid: TrainingData,
+}
+
+/// Imputed rows of the listings with a defined target, before preprocessing.
+struct Samples {
+    rows: Vec<Vec<f64>>,
+    targets: Vec<f64>,
+    prices: Vec<f64>,
+    sizes: Vec<f64>,
+}
+
+impl Samples {
+    /// Keeps the `rows` of the listings at `kept` whose `target` is defined.
+    fn collect(properties: &[Property], imputer: &Imputer, target: Target, rows: Vec<Vec<f64>>, kept: &[usize]) -> Self {
+        let mut samples = Samples { rows: Vec::new(), targets: Vec::new(), prices: Vec::new(), sizes: Vec::new() };
+        for (row, &i) in rows.into_iter().zip(kept) {
+            let size = size_for(imputer, &properties[i], &row);
+            if let Some(y) = target.value(properties[i].price_eur, Some(size)) {
+                samples.targets.push(y);
+                samples.prices.push(properties[i].price_eur);
+                samples.sizes.push(size);
+                samples.rows.push(row);
+            }
+        }
+        samples
+    }
+
+    fn prepare(self, pipeline: &Pipeline, column_names: Vec<String>) -> Result<TrainingData, Box<dyn Error>> {
+        if self.rows.is_empty() {
+            return Err("No listings to train on, scrape or import data first".into());
+        }
+
+        let x_data: Vec<Vec<f64>> = self.rows.iter().map(|row| pipeline.transform(row)).collect();
         let num_samples = x_data.len();
         let num_features = x_data[0].len();
 
         let x_array = Array2::from_shape_vec((num_samples, num_features), x_data.concat())?;
-        let y_array = Array2::from_shape_vec((num_samples, 1), y_data)?;
+        let y_array = Array1::from_vec(self.targets);
 
-        let dataset = Dataset::new(x_array, y_array).into_single_target();
-        Ok(dataset)
+        let dataset = Dataset::new(x_array, y_array).with_feature_names(column_names);
+        Ok(TrainingData { dataset, prices: self.prices, sizes: self.sizes })
     }
+}
 
-    fn train_model(dataset: &mut Dataset<f64, f64, ndarray::Ix1>) -> Result<ElasticNet<f64>, Box<dyn Error>> {
-        let k_folds = std::env::var("K_FOLDS")
-            .expect("Missing K_FOLDS env var")
-            .parse::<usize>()
-            .unwrap_or(10);
+impl Cervo {
+    /// Loads the model for `country`, `operation` and `property_type`, training it from the
+    /// stored listings of that market if none is saved yet. Sale models predict a price in
+    /// euros, rent models a monthly rent in euros.
+    pub fn new(country: Country, operation: Operation, property_type: PropertyType) -> Result<Self, Box<dyn Error>> {
+        let model_path = country.model_path(operation, property_type);
+        match Self::load_model(&model_path) {
+            Ok(cervo) => return Ok(cervo),
+            Err(e) if std::path::Path::new(&model_path).exists() => println!("{}", e),
+            Err(_) => {}
+        }
 
-        let mut best_model = None;
-        let mut best_score = f64::MAX;
-        let mut best_penalty = None;
-        let mut best_l1_ratio = None;
+        Self::train_and_record(country, operation, property_type)
+    }
 
-        let eval = |predicted: &Array1<f64>, expected: &ArrayView1<f64>| -> Result<Array0<f64>, linfa::Error> {
-            let r2_score = predicted.r2(expected);
-            r2_score.map(arr0)
-        };
+    /// Builds the training set, filling or dropping listings with missing values as the
+    /// imputer was configured to, then fits the preprocessing pipeline on the rows and
+    /// transforms them with it. Listings whose `target` is undefined are left out.
+    fn load_data(properties: &[Property], imputer: &Imputer, target: Target) -> Result<PreparedData, Box<dyn Error>> {
+        let (rows, kept, report) = imputer.training_rows(properties);
+        let samples = Samples::collect(properties, imputer, target, rows, &kept);
+
+        let pipeline = Pipeline::fit(
+            &imputer.column_names(),
+            &samples.rows,
+            Scaling::from_env()?,
+            &preprocessing::log_features_from_env()?,
+        );
+        let data = samples.prepare(&pipeline, imputer.column_names())?;
+        Ok((data, pipeline, report))
+    }
 
-        let penalties = &[0.01, 0.05, 0.1, 0.2, 0.3, 0.5, 1.0, 1.1, 1.2, 1.5, 2.0];
-        let l1_ratios = &[0.0, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+    /// Splits the listings into `k_folds` interleaved folds, listing `i` in fold `i % k_folds`.
+    /// Each fold's imputer, with its price prior and location encoder, comes from `fit_imputer`
+    /// on the fold's training listings; its held-out listings are filled like predictions.
+    fn folds(
+        properties: &[Property],
+        k_folds: usize,
+        target: Target,
+        fit_imputer: impl Fn(&[Property]) -> Imputer,
+    ) -> Result<Vec<Fold>, Box<dyn Error>> {
+        let n = properties.len();
+        if k_folds < 2 || k_folds > n {
+            return Err(format!("Can't split {} listings into {} folds", n, k_folds).into());
+        }
 
-        let total_iterations = penalties.len() * l1_ratios.len();
-        let mut completed_iterations = 0;
+        let mut folds = Vec::with_capacity(k_folds);
+        for fold in 0..k_folds {
+            let mut train = Vec::with_capacity(n);
+            let mut valid = Vec::with_capacity(n / k_folds + 1);
+            for (i, property) in properties.iter().enumerate() {
+                let half = if i % k_folds == fold { &mut valid } else { &mut train };
+                half.push(property.clone());
+            }
 
-        let start_time = Instant::now();
+            let imputer = fit_imputer(&train);
+            let (train, pipeline, _) = Self::load_data(&train, &imputer, target)?;
+            let (rows, kept, _) = imputer.holdout_rows(&valid);
+            let valid = Samples::collect(&valid, &imputer, target, rows, &kept)
+                .prepare(&pipeline, imputer.column_names())
+                .map_err(|_| format!("Fold {} of {} has no listings to score", fold + 1, k_folds))?;
+            folds.push(Fold { train, valid });
+        }
+        Ok(folds)
+    }
 
-        for &penalty in penalties {
-            for &l1_ratio in l1_ratios {
-                completed_iterations += 1;
-                let elapsed_time = start_time.elapsed().as_secs_f64();
+    /// Grid searches the hyperparameters of a `kind` model, keeping the candidate with the
+    /// best mean cross-validated `metric`. Scores are measured on prices in euros whatever
+    /// the target.
+    fn train_model(
+        data: &TrainingData,
+        folds: &[Fold],
+        kind: ModelKind,
+        metric: Metric,
+        target: Target,
+    ) -> Result<SelectedModel, Box<dyn Error>> {
+        let mut best_model = None;
+        let mut best_score = metric.worst();
+        let mut best_hyperparameters = None;
 
-                println!("Testing model with penalty: {} and l1_ratio: {}", penalty, l1_ratio);
+        let candidates = Hyperparameters::grid(kind);
+        let total_iterations = candidates.len();
+        let mut completed_iterations = 0;
 
-                let model_params = ElasticNet::params()
-                    .penalty(penalty)
-                    .l1_ratio(l1_ratio);
+        let start_time = Instant::now();
 
-                let results = match dataset.cross_validate(k_folds, &[model_params.clone()], &eval) {
-                    Ok(res) => res,
-                    Err(e) => {
-                        println!("Cross-validation error: {:?}", e);
-                        continue;
-                    }
-                };
+        for hyperparameters in candidates {
+            completed_iterations += 1;
+            let elapsed_time = start_time.elapsed().as_secs_f64();
 
-                let mean_mse = match results.mean() {
-                    Some(mse) => mse,
-                    None => {
-                        println!("Failed to compute mean MSE, skipping...");
-                        continue;
-                    }
-                };
-
-                if mean_mse < best_score {
-                    best_score = mean_mse;
-                    match model_params.fit(dataset) {
-                        Ok(model) => {
-                            best_model = Some(model);
-                            best_penalty = Some(penalty);
-                            best_l1_ratio = Some(l1_ratio);
-                        }
-                        Err(e) => println!("Model fitting error: {:?}", e),
+            println!("Testing model with {}", hyperparameters);
+
+            let mean_score = match cross_validate(&hyperparameters, folds, target, metric) {
+                Ok(score) if score.is_finite() => score,
+                Ok(_) => {
+                    println!("Failed to compute mean {}, skipping...", metric.name());
+                    continue;
+                }
+                Err(e) => {
+                    println!("Cross-validation error: {:?}", e);
+                    continue;
+                }
+            };
+            println!("Mean {}", metric.format(mean_score));
+
+            if metric.is_better(mean_score, best_score) {
+                match fit(&hyperparameters, &data.dataset, target) {
+                    Ok(fitted) => {
+                        best_score = mean_score;
+                        best_model = Some(fitted);
+                        best_hyperparameters = Some(hyperparameters);
                     }
+                    Err(e) => println!("Model fitting error: {:?}", e),
                 }
-
-                let eta = (elapsed_time / completed_iterations as f64) * total_iterations as f64 - elapsed_time;
-                println!(
-                    "Progress: {}/{} (ETA: {:.2} seconds)",
-                    completed_iterations, total_iterations, eta
-                );
             }
+
+            let eta = (elapsed_time / completed_iterations as f64) * total_iterations as f64 - elapsed_time;
+            println!(
+                "Progress: {}/{} (ETA: {:.2} seconds)",
+                completed_iterations, total_iterations, eta
+            );
         }
 
-        if let Some(model) = best_model {
-            Ok(model)
+        if let (Some((model, target)), Some(hyperparameters)) = (best_model, best_hyperparameters) {
+            println!("Selected model with {} ({})", hyperparameters, metric.format(best_score));
+            Ok(SelectedModel {
+                model,
+                target,
+                selection: Selection {
+                    metric,
+                    score: best_score,
+                    hyperparameters,
+                    k_folds: folds.len(),
+                    folds: FoldAssignment::Interleaved,
+                },
+            })
         } else {
             Err("No suitable model found".into())
         }
     }
 
     pub fn predict_price(&self, property: &Property) -> f64 {
-        let features = property.to_feature_vector();
+        let row = self.imputer.row(property);
+        let size = size_for(&self.imputer, property, &row);
+        let features = self.pipeline.transform(&row);
 
         let input_array = Array2::from_shape_vec((1, features.len()), features).unwrap();
         let prediction = self.model.predict(&input_array);
-        prediction[0]
+        self.target.price(prediction[0], size)
     }
 
-    pub fn train_and_save_model(filename: &str) -> Result<(), Box<dyn Error>> {
+    pub fn train_and_save_model(country: Country, operation: Operation, property_type: PropertyType) -> Result<(), Box<dyn Error>> {
         println!("Training a new model... This may take some time.");
 
-        let mut dataset = Self::load_data(filename)?;
-        let model = Self::train_model(&mut dataset)?;
-
-        Self::save_model(&model)?;
-        println!("Model training complete. Saved to {}", MODEL_FILE);
+        Self::train_and_record(country, operation, property_type)?;
+        println!("Model training complete. Saved to {}", country.model_path(operation, property_type));
         Ok(())
     }
 
-    fn save_model(model: &ElasticNet<f64>) -> Result<(), Box<dyn Error>> {
-        let serialized = serde_json::to_string(model)?;
-        let mut file = File::create(MODEL_FILE)?;
+    /// Trains on the stored listings of one country, operation and property type, saves the model and records the run.
+    fn train_and_record(country: Country, operation: Operation, property_type: PropertyType) -> Result<Self, Box<dyn Error>> {
+        let storage = Storage::open_default()?;
+        let properties = storage.properties_of_type(country, operation, property_type)?;
+        let model_path = country.model_path(operation, property_type);
+        let features = features::from_env()?;
+        let strategies = imputation::strategies_from_env(&features)?;
+        let kind = ModelKind::from_env()?;
+        let metric = Metric::from_env()?;
+        let target = Target::from_env()?;
+        let location = encoding::from_env()?;
+        let k_folds = std::env::var("K_FOLDS")
+            .expect("Missing K_FOLDS env var")
+            .parse::<usize>()
+            .unwrap_or(10);
+        let fit_imputer = |properties: &[Property]| {
+            let encoder = location
+                .clone()
+                .map(|(encoding, levels)| LocationEncoder::fit(encoding, levels, target, properties));
+            Imputer::fit(&features, &strategies, encoder, properties)
+        };
+        let imputer = fit_imputer(&properties);
+
+        let (data, pipeline, report) = Self::load_data(&properties, &imputer, target)?;
+        print!("{}", report);
+        let skipped = report.total_rows - report.dropped_rows - data.dataset.nsamples();
+        if skipped > 0 {
+            println!("Skipped {} listings without a positive price or size for the {} target", skipped, target.as_query());
+        }
+        println!("Preprocessing: {}, {} target", pipeline.describe(), target.as_query());
+        if let Some(location) = imputer.location() {
+            println!("Location: {}", location.describe());
+        }
+        let folds = Self::folds(&properties, k_folds, target, fit_imputer)?;
+        let selected = Self::train_model(&data, &folds, kind, metric, target)?;
+        let cervo = Self {
+            model: selected.model,
+            imputer,
+            pipeline,
+            target: selected.target,
+            selection: selected.selection,
+        };
+        cervo.save_model(&model_path)?;
+
+        storage.record_model_run(&ModelRun {
+            country,
+            operation,
+            property_type,
+            model_path,
+            samples: data.dataset.nsamples(),
+            target,
+            metric: cervo.selection.metric,
+            hyperparameters: cervo.selection.hyperparameters,
+            score: cervo.selection.score,
+        })?;
+
+        Ok(cervo)
+    }
+
+    fn save_model(&self, model_file: &str) -> Result<(), Box<dyn Error>> {
+        let serialized = serde_json::to_string(self)?;
+        let mut file = File::create(model_file)?;
         file.write_all(serialized.as_bytes())?;
-        println!("Saved model to {}", MODEL_FILE);
+        println!("Saved model to {}", model_file);
         Ok(())
     }
 
-    fn load_model() -> Result<ElasticNet<f64>, Box<dyn Error>> {
-        let mut file = File::open(MODEL_FILE)?;
+    /// Loads a saved model. Files from older versions (a bare `ElasticNet` trained on the
+    /// listing's own price per m²) fail to parse, so `new` retrains them.
+    fn load_model(model_file: &str) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(model_file)?;
         let mut buffer = String::new();
         file.read_to_string(&mut buffer).expect("Couldn't read to string buffer.");
 
-        let model: ElasticNet<f64> = serde_json::from_str(&buffer)?;
-        println!("Loaded model from {}", MODEL_FILE);
-        Ok(model)
+        let cervo: Self = serde_json::from_str(&buffer)
+            .map_err(|e| format!("{} was saved by an older version and needs retraining: {}", model_file, e))?;
+        let selection = &cervo.selection;
+        println!(
+            "Loaded {} model from {} ({} target, {}, {} over {} {} folds)",
+            selection.hyperparameters.kind().as_query(),
+            model_file,
+            cervo.target.target().as_query(),
+            selection.hyperparameters,
+            selection.metric.format(selection.score),
+            selection.k_folds,
+            selection.folds.name()
+        );
+        Ok(cervo)
+    }
+}
+
+/// Fits the model and the smearing factor of its back-transform on the same rows.
+fn fit(hyperparameters: &Hyperparameters, dataset: &TrainingSet, target: Target) -> Result<(Model, TargetTransform), Box<dyn Error>> {
+    let model = hyperparameters.fit(dataset)?;
+    let fitted = model.predict(dataset.records());
+    let transform = TargetTransform::fit(target, &fitted.to_vec(), &dataset.targets().to_vec());
+    Ok((model, transform))
+}
+
+/// Mean `metric` over the `folds`, each scored on its held-out listings by a model fitted on
+/// its training rows, comparing predicted and actual prices in euros.
+fn cross_validate(hyperparameters: &Hyperparameters, folds: &[Fold], target: Target, metric: Metric) -> Result<f64, Box<dyn Error>> {
+    let mut total = 0.0;
+    for fold in folds {
+        let (model, transform) = fit(hyperparameters, &fold.train.dataset, target)?;
+        let outputs = model.predict(fold.valid.dataset.records());
+        let predicted: Array1<f64> = outputs
+            .iter()
+            .zip(&fold.valid.sizes)
+            .map(|(&output, &size)| transform.price(output, size))
+            .collect();
+        let expected = Array1::from_vec(fold.valid.prices.clone());
+        total += metric.compute(predicted.view(), expected.view());
+    }
+
+    Ok(total / folds.len() as f64)
+}
+
+/// Size used to turn a per-m² prediction into a price: the listing's own, else the value
+/// imputed into its row.
+fn size_for(imputer: &Imputer, property: &Property, row: &[f64]) -> f64 {
+    property
+        .size_sqm
+        .or_else(|| imputer.features().iter().position(|f| *f == Feature::Size).map(|i| row[i]))
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::encoding::{LocationEncoding, LocationLevel};
+    use crate::core::imputation::MissingStrategy;
+    use crate::core::types::Floor;
+
+    fn listing(code: usize, price: f64, neighborhood: &str) -> Property {
+        Property {
+            property_code: code.to_string(),
+            price_eur: price,
+            size_sqm: Some(50.0 + code as f64),
+            floor: Floor::Unknown,
+            address: String::new(),
+            province: "Zaragoza".to_string(),
+            municipality: "Zaragoza".to_string(),
+            district: "Centro".to_string(),
+            neighborhood: neighborhood.to_string(),
+            latitude: None,
+            longitude: None,
+            has_lift: None,
+            price_per_sqm: None,
+            rooms: None,
+            bathrooms: None,
+            swimming_pool: None,
+            garden: None,
+            garage: None,
+            url: String::new(),
+            property_type: PropertyType::Homes,
+            status: None,
+            exterior: None,
+            energy_rating: None,
+            num_photos: None,
+            description: None,
+            has_parking_space: None,
+            parking_included: None,
+            new_development: None,
+            sub_typology: None,
+        }
+    }
+
+    fn folds(properties: &[Property]) -> Vec<Fold> {
+        let features = [Feature::Size, Feature::AreaPricePerSqm];
+        let strategies = [MissingStrategy::Median, MissingStrategy::Median];
+        let fit_imputer = |properties: &[Property]| {
+            let encoder = LocationEncoder::fit(LocationEncoding::Target, LocationLevel::ALL.to_vec(), Target::Price, properties);
+            Imputer::fit(&features, &strategies, Some(encoder), properties)
+        };
+        Cervo::folds(properties, 4, Target::Price, fit_imputer).unwrap()
+    }
+
+    #[test]
+    fn held_out_rows_do_not_see_their_own_price() {
+        let mut properties: Vec<Property> = (0..20)
+            .map(|i| listing(i, 100_000.0 + 5_000.0 * i as f64, if i % 3 == 0 { "Pilar" } else { "Magdalena" }))
+            .collect();
+        let before = folds(&properties);
+
+        properties[0].price_eur = 5_000_000.0;
+        let after = folds(&properties);
+
+        // Listing 0 is the first held-out row of fold 0; only its price may change.
+        assert_eq!(before[0].valid.dataset.records().row(0), after[0].valid.dataset.records().row(0));
+        assert_eq!(after[0].valid.prices[0], 5_000_000.0);
+        // Every other fold trains on listing 0, so its prior and encoding do move.
+        assert_ne!(before[1].valid.dataset.records(), after[1].valid.dataset.records());
+    }
+
+    #[test]
+    fn folds_interleave_the_listings() {
+        let properties: Vec<Property> = (0..10).map(|i| listing(i, 100_000.0, "Pilar")).collect();
+        let folds = folds(&properties);
+
+        let held_out: Vec<usize> = folds.iter().map(|fold| fold.valid.prices.len()).collect();
+        assert_eq!(held_out, vec![3, 3, 2, 2]);
+        assert!(folds.iter().all(|fold| fold.train.prices.len() + fold.valid.prices.len() == 10));
+        assert!(Cervo::folds(&properties, 11, Target::Price, |p: &[Property]| Imputer::fit(&[Feature::Size], &[MissingStrategy::Median], None, p)).is_err());
     }
 }
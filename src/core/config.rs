@@ -0,0 +1,147 @@
+use crate::core::scrapers::band::SplitDimension;
+use crate::core::types::{Country, Operation, PropertyType};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+pub const DEFAULT_CONFIG_FILE: &str = "scrape_config.toml";
+
+/// What to scrape, read from `scrape_config.toml` (or the file in `SCRAPE_CONFIG`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScrapeConfig {
+    /// Page budget per search for locations that don't set their own `max_pages`, shared by
+    /// every band a search is split into.
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    /// Result pages the API serves for one search; searches with more are split.
+    #[serde(default = "default_api_page_limit")]
+    pub api_page_limit: u32,
+    /// Dimension truncated searches are split on, unless a location sets its own.
+    #[serde(default = "default_split_by")]
+    pub split_by: SplitDimension,
+    /// How many times a search may be halved; 0 disables splitting.
+    #[serde(default = "default_max_split_depth")]
+    pub max_split_depth: u32,
+    pub locations: Vec<LocationConfig>,
+    #[serde(default)]
+    pub api: ApiConfig,
+}
+
+/// Where the Idealista backend sends requests. Defaults to the RapidAPI endpoint; point
+/// `base_url` at a local mock server to run scrapes in integration tests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Falls back to the `IDEALISTA_BASE_API_URL` env var.
+    pub base_url: Option<String>,
+    /// Header carrying the API key; an empty string sends no key at all.
+    pub api_key_header: String,
+    /// Env var holding the API key, so secrets stay out of the config file.
+    pub api_key_env: String,
+    /// Extra headers sent with every request.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            api_key_header: "x-rapidapi-key".to_string(),
+            api_key_env: "IDEALISTA_API_KEY".to_string(),
+            headers: BTreeMap::from([("x-rapidapi-host".to_string(), "idealista7.p.rapidapi.com".to_string())]),
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn base_url(&self) -> Result<String, Box<dyn Error>> {
+        match &self.base_url {
+            Some(base_url) => Ok(base_url.trim_end_matches('/').to_string()),
+            None => std::env::var("IDEALISTA_BASE_API_URL")
+                .map_err(|_| "Missing IDEALISTA_BASE_API_URL env var and no api.base_url in the scrape config".into()),
+        }
+    }
+
+    /// Every header to send: the configured ones plus the API key, if auth is enabled.
+    pub fn request_headers(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let mut headers: Vec<(String, String)> = self.headers.clone().into_iter().collect();
+
+        if !self.api_key_header.is_empty() {
+            let api_key = std::env::var(&self.api_key_env)
+                .map_err(|_| format!("Missing {} env var", self.api_key_env))?;
+            headers.push((self.api_key_header.clone(), api_key));
+        }
+
+        Ok(headers)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocationConfig {
+    pub name: String,
+    /// Idealista site the location belongs to, defaults to Spain.
+    #[serde(default)]
+    pub country: Country,
+    #[serde(default = "default_property_types")]
+    pub property_types: Vec<PropertyType>,
+    #[serde(default = "default_operations")]
+    pub operations: Vec<Operation>,
+    pub max_pages: Option<u32>,
+    pub split_by: Option<SplitDimension>,
+}
+
+impl ScrapeConfig {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read scrape config {}: {}", path, e))?;
+        let config: ScrapeConfig = toml::from_str(&contents)?;
+        Ok(config)
+    }
+
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let path = std::env::var("SCRAPE_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_string());
+        Self::load(&path)
+    }
+
+    /// Locations configured for the given country and operation.
+    pub fn locations_for(&self, country: Country, operation: Operation) -> impl Iterator<Item = &LocationConfig> {
+        self.locations
+            .iter()
+            .filter(move |location| location.country == country && location.operations.contains(&operation))
+    }
+
+    pub fn max_pages_for(&self, location: &LocationConfig) -> u32 {
+        location.max_pages.unwrap_or(self.max_pages)
+    }
+
+    pub fn split_by_for(&self, location: &LocationConfig) -> SplitDimension {
+        location.split_by.unwrap_or(self.split_by)
+    }
+}
+
+fn default_max_pages() -> u32 {
+    10
+}
+
+fn default_api_page_limit() -> u32 {
+    50
+}
+
+fn default_split_by() -> SplitDimension {
+    SplitDimension::Price
+}
+
+fn default_max_split_depth() -> u32 {
+    4
+}
+
+fn default_property_types() -> Vec<PropertyType> {
+    vec![PropertyType::Homes]
+}
+
+fn default_operations() -> Vec<Operation> {
+    vec![Operation::Sale]
+}
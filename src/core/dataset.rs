@@ -0,0 +1,195 @@
+use crate::core::history::ListingEvent;
+use crate::core::types::{Floor, Property, PropertyType};
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use csv::{ReaderBuilder, StringRecord, Writer};
+
+pub const CSV_HEADERS: [&str; 32] = [
+    "Property Code", "Price (€)", "Size (m²)", "Floor", "Address", "Province",
+    "Municipality", "District", "Neighborhood", "Latitude", "Longitude", "Has Lift",
+    "Price by Area", "Rooms", "Bathrooms", "Swimming Pool", "Garden", "Garage", "URL",
+    "First Seen", "Last Seen", "Delisted At", "Property Type", "Status", "Exterior",
+    "Energy Rating", "Photos", "Description", "Parking Space", "Parking Included",
+    "New Development", "Subtype",
+];
+
+/// A search (configured location) and property type a scrape covered completely: every
+/// listing Idealista had for it was returned, so a listing that search last returned and
+/// that is missing from the batch is gone.
+pub type Scope = (String, PropertyType);
+
+/// What a scrape covered, for the upsert to tell a gone listing from one it never asked for.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// Search that returned each scraped property code, stored with the listing.
+    pub searches: HashMap<String, String>,
+    pub complete: HashSet<Scope>,
+}
+
+/// A row of the dataset CSV: the listing plus when scraping first and last saw it.
+#[derive(Debug, Clone)]
+pub struct StoredListing {
+    pub property: Property,
+    /// RFC 3339 timestamp, `None` for rows written before timestamps were tracked.
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    /// Set when a complete scrape of the search that last returned the listing no longer returned it.
+    pub delisted_at: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct UpsertSummary {
+    pub inserted: usize,
+    pub updated: usize,
+    pub delisted: usize,
+    /// Lifecycle events produced by this upsert, to be stored as price history.
+    pub events: Vec<ListingEvent>,
+}
+
+/// Reads a dataset CSV, keeping only the last row for each property code.
+pub fn read_listings(csv_file_path: &str) -> Result<Vec<StoredListing>, Box<dyn Error>> {
+    if !Path::new(csv_file_path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(csv_file_path)?;
+    let mut rdr = ReaderBuilder::new().has_headers(true).flexible(true).from_reader(file);
+
+    let mut listings: Vec<StoredListing> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for result in rdr.records() {
+        let listing = from_record(&result?)?;
+        match index.get(&listing.property.property_code) {
+            Some(&i) => {
+                let first_seen = listings[i].first_seen.take();
+                listings[i] = StoredListing {
+                    first_seen: first_seen.or(listing.first_seen),
+                    ..listing
+                };
+            }
+            None => {
+                index.insert(listing.property.property_code.clone(), listings.len());
+                listings.push(listing);
+            }
+        }
+    }
+
+    Ok(listings)
+}
+
+/// Writes listings to a dataset CSV, replacing the file.
+pub fn write_listings(csv_file_path: &str, listings: &[StoredListing]) -> Result<(), Box<dyn Error>> {
+    let tmp_path = format!("{}.tmp", csv_file_path);
+    let mut writer = Writer::from_path(&tmp_path)?;
+
+    writer.write_record(CSV_HEADERS)?;
+    for listing in listings {
+        writer.write_record(to_record(listing))?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&tmp_path, csv_file_path)?;
+    Ok(())
+}
+
+fn to_record(listing: &StoredListing) -> [String; 32] {
+    let property = &listing.property;
+    [
+        property.property_code.clone(),
+        property.price_eur.to_string(),
+        or_na(property.size_sqm),
+        or_na(property.floor.code()),
+        text_or_na(&property.address),
+        text_or_na(&property.province),
+        text_or_na(&property.municipality),
+        text_or_na(&property.district),
+        text_or_na(&property.neighborhood),
+        or_na(property.latitude),
+        or_na(property.longitude),
+        or_na(property.has_lift),
+        or_na(property.price_per_sqm),
+        or_na(property.rooms),
+        or_na(property.bathrooms),
+        or_na(property.swimming_pool),
+        or_na(property.garden),
+        or_na(property.garage),
+        text_or_na(&property.url),
+        or_na(listing.first_seen.as_ref()),
+        or_na(listing.last_seen.as_ref()),
+        or_na(listing.delisted_at.as_ref()),
+        property.property_type.as_query().to_string(),
+        or_na(property.status.as_ref()),
+        or_na(property.exterior),
+        or_na(property.energy_rating.as_ref()),
+        or_na(property.num_photos),
+        or_na(property.description.as_ref()),
+        or_na(property.has_parking_space),
+        or_na(property.parking_included),
+        or_na(property.new_development),
+        or_na(property.sub_typology.as_ref()),
+    ]
+}
+
+fn from_record(record: &StringRecord) -> Result<StoredListing, Box<dyn Error>> {
+    let text = |i: usize| field(record, i).unwrap_or_default().to_string();
+    let number = |i: usize| field(record, i).and_then(|v| v.parse::<f64>().ok());
+    let flag = |i: usize| field(record, i).and_then(|v| v.parse::<bool>().ok());
+
+    let property = Property {
+        property_code: record.get(0).ok_or("Missing property code")?.to_string(),
+        price_eur: record.get(1).ok_or("Missing price")?.parse()?,
+        size_sqm: number(2),
+        floor: field(record, 3).map_or(Floor::Unknown, Floor::parse),
+        address: text(4),
+        province: text(5),
+        municipality: text(6),
+        district: text(7),
+        neighborhood: text(8),
+        latitude: number(9),
+        longitude: number(10),
+        has_lift: flag(11),
+        price_per_sqm: number(12),
+        rooms: number(13).map(|r| r as u32),
+        bathrooms: number(14).map(|b| b as u32),
+        swimming_pool: flag(15),
+        garden: flag(16),
+        garage: flag(17),
+        url: text(18),
+        // Datasets written before property types were scraped only held homes.
+        property_type: field(record, 22).and_then(PropertyType::parse).unwrap_or_default(),
+        status: field(record, 23).map(str::to_string),
+        exterior: flag(24),
+        energy_rating: field(record, 25).map(str::to_string),
+        num_photos: number(26).map(|n| n as u32),
+        description: field(record, 27).map(str::to_string),
+        has_parking_space: flag(28),
+        parking_included: flag(29),
+        new_development: flag(30),
+        sub_typology: field(record, 31).map(str::to_string),
+    };
+
+    Ok(StoredListing {
+        property,
+        first_seen: field(record, 19).map(str::to_string),
+        last_seen: field(record, 20).map(str::to_string),
+        delisted_at: field(record, 21).map(str::to_string),
+    })
+}
+
+/// A CSV field, or `None` when it's absent, empty or `N/A`.
+fn field(record: &StringRecord, i: usize) -> Option<&str> {
+    record.get(i).map(str::trim).filter(|v| !v.is_empty() && *v != "N/A")
+}
+
+fn or_na<T: ToString>(value: Option<T>) -> String {
+    value.map_or("N/A".to_string(), |v| v.to_string())
+}
+
+fn text_or_na(value: &str) -> String {
+    if value.is_empty() { "N/A".to_string() } else { value.to_string() }
+}
@@ -0,0 +1,296 @@
+use crate::core::preprocessing::Target;
+use crate::core::types::Property;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Listings a category needs in training to get its own one-hot column.
+const ONE_HOT_MIN_LISTINGS: usize = 5;
+/// Weight, in listings, of the parent mean when smoothing an area's mean target.
+const SMOOTHING: f64 = 10.0;
+/// Folds for the out-of-fold target encoding of training listings.
+const ENCODING_FOLDS: usize = 5;
+
+/// A level of the location hierarchy, coarsest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationLevel {
+    Province,
+    Municipality,
+    District,
+    Neighbourhood,
+}
+
+impl LocationLevel {
+    pub const ALL: [LocationLevel; 4] = [
+        LocationLevel::Province,
+        LocationLevel::Municipality,
+        LocationLevel::District,
+        LocationLevel::Neighbourhood,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocationLevel::Province => "province",
+            LocationLevel::Municipality => "municipality",
+            LocationLevel::District => "district",
+            LocationLevel::Neighbourhood => "neighbourhood",
+        }
+    }
+
+    /// Key of the listing's area at this level. Districts and neighbourhoods include their
+    /// municipality, as names like "Centro" repeat across cities.
+    fn key(&self, property: &Property) -> Option<String> {
+        let known = |name: &str| !name.is_empty() && name != "N/A";
+        let municipality = known(&property.municipality);
+        let district = municipality && known(&property.district);
+        match self {
+            LocationLevel::Province => known(&property.province).then(|| format!("p|{}", property.province)),
+            LocationLevel::Municipality => municipality.then(|| format!("m|{}", property.municipality)),
+            LocationLevel::District => district.then(|| format!("d|{}|{}", property.municipality, property.district)),
+            LocationLevel::Neighbourhood => (district && known(&property.neighborhood)).then(|| {
+                format!("n|{}|{}|{}", property.municipality, property.district, property.neighborhood)
+            }),
+        }
+    }
+}
+
+/// How the location columns become model inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationEncoding {
+    /// A 0/1 column per area with at least `ONE_HOT_MIN_LISTINGS` listings.
+    OneHot,
+    /// Mean target of the area, smoothed towards the overall mean.
+    Target,
+    /// Mean target of the area, smoothed towards the encoding of the enclosing area.
+    Hierarchical,
+}
+
+impl LocationEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocationEncoding::OneHot => "one_hot",
+            LocationEncoding::Target => "target",
+            LocationEncoding::Hierarchical => "hierarchical",
+        }
+    }
+}
+
+/// An encoding and the levels it applies to.
+pub type EncodingConfig = (LocationEncoding, Vec<LocationLevel>);
+
+/// `LOCATION_ENCODING` (`none`, `one_hot`, `target` or `hierarchical`, default none) and the
+/// levels it applies to, from `LOCATION_LEVELS` (comma separated, default all four).
+pub fn from_env() -> Result<Option<EncodingConfig>, Box<dyn Error>> {
+    let encoding = match std::env::var("LOCATION_ENCODING").unwrap_or_default().trim().to_lowercase().as_str() {
+        "" | "none" => return Ok(None),
+        "one_hot" => LocationEncoding::OneHot,
+        "target" => LocationEncoding::Target,
+        "hierarchical" => LocationEncoding::Hierarchical,
+        other => {
+            return Err(format!("Unknown LOCATION_ENCODING {}, expected none, one_hot, target or hierarchical", other).into());
+        }
+    };
+
+    let configured = std::env::var("LOCATION_LEVELS").unwrap_or_default();
+    let mut levels = Vec::new();
+    for name in configured.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let level = LocationLevel::ALL
+            .into_iter()
+            .find(|level| level.name() == name)
+            .ok_or_else(|| format!("Unknown location level {}", name))?;
+        levels.push(level);
+    }
+    if levels.is_empty() {
+        levels = LocationLevel::ALL.to_vec();
+    }
+    levels.sort();
+    levels.dedup();
+
+    Ok(Some((encoding, levels)))
+}
+
+/// Mean targets per area key, one map per level.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TargetMeans {
+    overall: f64,
+    by_level: Vec<HashMap<String, f64>>,
+}
+
+/// Encodes a listing's province, municipality, district and neighbourhood. Saved with
+/// the model so predictions use the categories and means seen in training.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationEncoder {
+    encoding: LocationEncoding,
+    levels: Vec<LocationLevel>,
+    /// One-hot categories per level.
+    categories: Vec<Vec<String>>,
+    /// Target means from every training listing, used for predictions.
+    means: TargetMeans,
+    /// Target encodings of the training listings by property code, each computed without
+    /// the listing's own fold so its price doesn't leak into its features.
+    #[serde(skip)]
+    out_of_fold: HashMap<String, Vec<f64>>,
+}
+
+impl LocationEncoder {
+    /// Fits the encoder on the listings with a defined `target` value.
+    pub fn fit(encoding: LocationEncoding, levels: Vec<LocationLevel>, target: Target, properties: &[Property]) -> Self {
+        let mut encoder = Self {
+            encoding,
+            levels,
+            categories: Vec::new(),
+            means: TargetMeans::default(),
+            out_of_fold: HashMap::new(),
+        };
+
+        let samples: Vec<(Vec<Option<String>>, Option<f64>)> = properties
+            .iter()
+            .map(|p| (encoder.keys(p), target.value(p.price_eur, p.size_sqm)))
+            .collect();
+
+        if encoding == LocationEncoding::OneHot {
+            encoder.categories = (0..encoder.levels.len())
+                .map(|level| {
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for key in samples.iter().filter_map(|(keys, _)| keys[level].as_deref()) {
+                        *counts.entry(key).or_default() += 1;
+                    }
+                    let mut categories: Vec<String> = counts
+                        .into_iter()
+                        .filter(|(_, count)| *count >= ONE_HOT_MIN_LISTINGS)
+                        .map(|(key, _)| key.to_string())
+                        .collect();
+                    categories.sort();
+                    categories
+                })
+                .collect();
+            return encoder;
+        }
+
+        encoder.means = encoder.target_means(samples.iter().map(|(keys, y)| (keys, *y)));
+        for fold in 0..ENCODING_FOLDS {
+            let means = encoder.target_means(
+                samples
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| i % ENCODING_FOLDS != fold)
+                    .map(|(_, (keys, y))| (keys, *y)),
+            );
+            for (i, property) in properties.iter().enumerate().skip(fold).step_by(ENCODING_FOLDS) {
+                let encoded = encoder.encode_with(&means, &samples[i].0);
+                encoder.out_of_fold.insert(property.property_code.clone(), encoded);
+            }
+        }
+
+        encoder
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        match self.encoding {
+            LocationEncoding::OneHot => self
+                .levels
+                .iter()
+                .zip(&self.categories)
+                .flat_map(|(level, categories)| categories.iter().map(move |key| format!("{}={}", level.name(), key)))
+                .collect(),
+            _ => self
+                .levels
+                .iter()
+                .map(|level| format!("{}_{}", level.name(), self.encoding.name()))
+                .collect(),
+        }
+    }
+
+    /// Encoded columns for a listing. `training` listings get their out-of-fold encoding.
+    pub fn encode(&self, property: &Property, training: bool) -> Vec<f64> {
+        if training && let Some(encoded) = self.out_of_fold.get(&property.property_code) {
+            return encoded.clone();
+        }
+
+        let keys = self.keys(property);
+        match self.encoding {
+            LocationEncoding::OneHot => keys
+                .iter()
+                .zip(&self.categories)
+                .flat_map(|(key, categories)| {
+                    categories
+                        .iter()
+                        .map(move |category| if key.as_deref() == Some(category.as_str()) { 1.0 } else { 0.0 })
+                })
+                .collect(),
+            _ => self.encode_with(&self.means, &keys),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let levels: Vec<&str> = self.levels.iter().map(|level| level.name()).collect();
+        format!("{} encoding of {}", self.encoding.name(), levels.join(", "))
+    }
+
+    fn keys(&self, property: &Property) -> Vec<Option<String>> {
+        self.levels.iter().map(|level| level.key(property)).collect()
+    }
+
+    /// Smoothed mean target per area: `(sum + SMOOTHING * prior) / (count + SMOOTHING)`,
+    /// where the prior is the overall mean, or the enclosing area's mean when hierarchical.
+    fn target_means<'a>(&self, samples: impl Iterator<Item = (&'a Vec<Option<String>>, Option<f64>)>) -> TargetMeans {
+        let samples: Vec<(&Vec<Option<String>>, f64)> = samples.filter_map(|(keys, y)| y.map(|y| (keys, y))).collect();
+        let overall = if samples.is_empty() {
+            0.0
+        } else {
+            samples.iter().map(|(_, y)| y).sum::<f64>() / samples.len() as f64
+        };
+
+        let mut by_level: Vec<HashMap<String, f64>> = Vec::with_capacity(self.levels.len());
+        for level in 0..self.levels.len() {
+            // (sum, count, prior) per key; listings of one area share the enclosing area.
+            let mut totals: HashMap<&str, (f64, usize, f64)> = HashMap::new();
+            for (keys, y) in &samples {
+                let Some(key) = keys[level].as_deref() else { continue };
+                let prior = match self.encoding {
+                    LocationEncoding::Hierarchical => parent_value(&by_level, keys, level, overall),
+                    _ => overall,
+                };
+                let entry = totals.entry(key).or_insert((0.0, 0, prior));
+                entry.0 += y;
+                entry.1 += 1;
+            }
+
+            by_level.push(
+                totals
+                    .into_iter()
+                    .map(|(key, (sum, count, prior))| {
+                        (key.to_string(), (sum + SMOOTHING * prior) / (count as f64 + SMOOTHING))
+                    })
+                    .collect(),
+            );
+        }
+
+        TargetMeans { overall, by_level }
+    }
+
+    /// Unseen areas take the overall mean, or the enclosing area's encoding when hierarchical.
+    fn encode_with(&self, means: &TargetMeans, keys: &[Option<String>]) -> Vec<f64> {
+        let mut encoded = Vec::with_capacity(keys.len());
+        for (level, key) in keys.iter().enumerate() {
+            let fallback = match self.encoding {
+                LocationEncoding::Hierarchical => encoded.last().copied().unwrap_or(means.overall),
+                _ => means.overall,
+            };
+            encoded.push(key.as_ref().and_then(|key| means.by_level[level].get(key)).copied().unwrap_or(fallback));
+        }
+        encoded
+    }
+}
+
+/// Encoding of the nearest enclosing area that has one, else `overall`.
+fn parent_value(by_level: &[HashMap<String, f64>], keys: &[Option<String>], level: usize, overall: f64) -> f64 {
+    (0..level)
+        .rev()
+        .find_map(|parent| keys[parent].as_ref().and_then(|key| by_level[parent].get(key)))
+        .copied()
+        .unwrap_or(overall)
+}
@@ -0,0 +1,203 @@
+use crate::core::types::{Floor, Property};
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A model input column. The features a model was trained on are saved with it, so
+/// predictions build the same columns in the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Size,
+    Floor,
+    FloorUnknown,
+    GroundFloor,
+    TopFloor,
+    Latitude,
+    Longitude,
+    HasLift,
+    PricePerSqm,
+    AreaPricePerSqm,
+    Rooms,
+    Bathrooms,
+    SwimmingPool,
+    Garden,
+    Garage,
+    Exterior,
+    GoodCondition,
+    NeedsRenovation,
+    EnergyRating,
+    Photos,
+    ParkingSpace,
+    ParkingIncluded,
+    NewDevelopment,
+}
+
+/// Features every model uses.
+pub const BASE_FEATURES: [Feature; 14] = [
+    Feature::Size,
+    Feature::Floor,
+    Feature::FloorUnknown,
+    Feature::GroundFloor,
+    Feature::TopFloor,
+    Feature::Latitude,
+    Feature::Longitude,
+    Feature::HasLift,
+    Feature::AreaPricePerSqm,
+    Feature::Rooms,
+    Feature::Bathrooms,
+    Feature::SwimmingPool,
+    Feature::Garden,
+    Feature::Garage,
+];
+
+/// Features derived from the price itself. A listing's price per m² is its price divided
+/// by its size, so a model trained on it learns the target and can't price a property
+/// whose price is unknown. Only allowed with `TRAINING_MODE=leaky`.
+pub const LEAKY_FEATURES: [Feature; 1] = [Feature::PricePerSqm];
+
+/// Features that can be switched on with `MODEL_EXTRA_FEATURES`.
+pub const OPTIONAL_FEATURES: [Feature; 8] = [
+    Feature::Exterior,
+    Feature::GoodCondition,
+    Feature::NeedsRenovation,
+    Feature::EnergyRating,
+    Feature::Photos,
+    Feature::ParkingSpace,
+    Feature::ParkingIncluded,
+    Feature::NewDevelopment,
+];
+
+impl Feature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Size => "size",
+            Feature::Floor => "floor",
+            Feature::FloorUnknown => "floor_unknown",
+            Feature::GroundFloor => "ground_floor",
+            Feature::TopFloor => "top_floor",
+            Feature::Latitude => "latitude",
+            Feature::Longitude => "longitude",
+            Feature::HasLift => "has_lift",
+            Feature::PricePerSqm => "price_per_sqm",
+            Feature::AreaPricePerSqm => "area_price_per_sqm",
+            Feature::Rooms => "rooms",
+            Feature::Bathrooms => "bathrooms",
+            Feature::SwimmingPool => "swimming_pool",
+            Feature::Garden => "garden",
+            Feature::Garage => "garage",
+            Feature::Exterior => "exterior",
+            Feature::GoodCondition => "good_condition",
+            Feature::NeedsRenovation => "needs_renovation",
+            Feature::EnergyRating => "energy_rating",
+            Feature::Photos => "photos",
+            Feature::ParkingSpace => "parking_space",
+            Feature::ParkingIncluded => "parking_included",
+            Feature::NewDevelopment => "new_development",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        BASE_FEATURES
+            .into_iter()
+            .chain(OPTIONAL_FEATURES)
+            .chain(LEAKY_FEATURES)
+            .find(|feature| feature.name() == value)
+    }
+
+    /// Value of the feature for `property`, `None` when the listing doesn't say.
+    /// `AreaPricePerSqm` depends on other listings and comes from the imputer's `PricePrior`.
+    pub fn value(&self, property: &Property) -> Option<f64> {
+        let flag = |value: Option<bool>| value.map(|v| if v { 1.0 } else { 0.0 });
+
+        match self {
+            Feature::Size => property.size_sqm,
+            Feature::Floor => property.floor.level(),
+            // Lets the model tell an unknown floor apart from the ground floor, which both have level 0.
+            Feature::FloorUnknown => Some(if property.floor.is_unknown() { 1.0 } else { 0.0 }),
+            Feature::GroundFloor => Some(if property.floor == Floor::Ground { 1.0 } else { 0.0 }),
+            // Idealista marks áticos as penthouses; the floor code alone can't say it's the top one.
+            Feature::TopFloor => property.sub_typology.as_deref().map(|s| if s == "penthouse" { 1.0 } else { 0.0 }),
+            Feature::Latitude => property.latitude,
+            Feature::Longitude => property.longitude,
+            Feature::HasLift => flag(property.has_lift),
+            Feature::PricePerSqm => property.price_per_sqm,
+            Feature::AreaPricePerSqm => None,
+            // Garages, land and the like have no rooms, so for them a missing count is 0, not unknown.
+            Feature::Rooms if !property.property_type.has_rooms() => Some(property.rooms.map_or(0.0, f64::from)),
+            Feature::Bathrooms if !property.property_type.has_rooms() => Some(property.bathrooms.map_or(0.0, f64::from)),
+            Feature::Rooms => property.rooms.map(f64::from),
+            Feature::Bathrooms => property.bathrooms.map(f64::from),
+            Feature::SwimmingPool => flag(property.swimming_pool),
+            Feature::Garden => flag(property.garden),
+            Feature::Garage => flag(property.garage),
+            Feature::Exterior => flag(property.exterior),
+            Feature::GoodCondition => property.status.as_deref().map(|s| if s == "good" { 1.0 } else { 0.0 }),
+            Feature::NeedsRenovation => property.status.as_deref().map(|s| if s == "renew" { 1.0 } else { 0.0 }),
+            Feature::EnergyRating => property.energy_rating.as_deref().and_then(energy_score),
+            Feature::Photos => property.num_photos.map(f64::from),
+            Feature::ParkingSpace => flag(property.has_parking_space),
+            Feature::ParkingIncluded => flag(property.parking_included),
+            Feature::NewDevelopment => flag(property.new_development),
+        }
+    }
+}
+
+/// Whether training may use features derived from the price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainingMode {
+    /// Only features known before a property is priced. The default.
+    Honest,
+    /// Adds the listing's own price per m², as models did originally. Scores look far
+    /// better than the model can do on a property without a price; useful for comparison only.
+    Leaky,
+}
+
+impl TrainingMode {
+    /// Reads `TRAINING_MODE` (`honest` or `leaky`), defaulting to honest.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("TRAINING_MODE").unwrap_or_default().trim() {
+            "" | "honest" => Ok(TrainingMode::Honest),
+            "leaky" => Ok(TrainingMode::Leaky),
+            other => Err(format!("Unknown TRAINING_MODE {}, expected honest or leaky", other).into()),
+        }
+    }
+}
+
+/// Base features plus the optional ones listed in `MODEL_EXTRA_FEATURES`
+/// (comma separated feature names, or `all`), and the leaky ones in leaky mode.
+pub fn from_env() -> Result<Vec<Feature>, Box<dyn Error>> {
+    let mode = TrainingMode::from_env()?;
+    let mut features = BASE_FEATURES.to_vec();
+    let extra = std::env::var("MODEL_EXTRA_FEATURES").unwrap_or_default();
+
+    for name in extra.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if name == "all" {
+            features.extend(OPTIONAL_FEATURES);
+            continue;
+        }
+        let feature = Feature::parse(name).ok_or_else(|| format!("Unknown model feature {}", name))?;
+        if LEAKY_FEATURES.contains(&feature) && mode == TrainingMode::Honest {
+            return Err(format!("{} is derived from the price, set TRAINING_MODE=leaky to train on it", name).into());
+        }
+        features.push(feature);
+    }
+
+    if mode == TrainingMode::Leaky {
+        features.extend(LEAKY_FEATURES);
+    }
+
+    let mut seen = Vec::new();
+    features.retain(|feature| {
+        let new = !seen.contains(feature);
+        seen.push(*feature);
+        new
+    });
+    Ok(features)
+}
+
+/// `a` = 7 down to `g` = 1, so a better rating is a larger number.
+fn energy_score(rating: &str) -> Option<f64> {
+    let position = ["g", "f", "e", "d", "c", "b", "a"].iter().position(|r| *r == rating)?;
+    Some(position as f64 + 1.0)
+}
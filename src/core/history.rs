@@ -0,0 +1,136 @@
+use crate::core::dataset::StoredListing;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use csv::Reader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Listed,
+    PriceChange,
+    Delisted,
+    Relisted,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Listed => "Listed",
+            EventKind::PriceChange => "PriceChange",
+            EventKind::Delisted => "Delisted",
+            EventKind::Relisted => "Relisted",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "Listed" => Some(EventKind::Listed),
+            "PriceChange" => Some(EventKind::PriceChange),
+            "Delisted" => Some(EventKind::Delisted),
+            "Relisted" => Some(EventKind::Relisted),
+            _ => None,
+        }
+    }
+}
+
+/// One row of a listing's history. Prices are `None` where they don't apply,
+/// e.g. `old_price` on `Listed`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingEvent {
+    #[serde(rename = "Property Code")]
+    pub property_code: String,
+    #[serde(rename = "Date")]
+    pub date: String,
+    #[serde(rename = "Event")]
+    pub kind: EventKind,
+    #[serde(rename = "Old Price")]
+    pub old_price: Option<f64>,
+    #[serde(rename = "New Price")]
+    pub new_price: Option<f64>,
+}
+
+/// Reads a history CSV written by earlier, CSV-backed versions.
+pub fn read_events(history_file_path: &str) -> Result<Vec<ListingEvent>, Box<dyn Error>> {
+    if !Path::new(history_file_path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut rdr = Reader::from_path(history_file_path)?;
+    let mut events = Vec::new();
+    for result in rdr.deserialize() {
+        events.push(result?);
+    }
+    Ok(events)
+}
+
+#[derive(Debug, Default)]
+pub struct CityActivity {
+    pub city: String,
+    pub listings: usize,
+    pub delisted: usize,
+    /// Mean days between first seen and delisting (or last seen for active listings).
+    pub avg_days_on_market: f64,
+    pub price_cuts: usize,
+    /// Mean size of a price cut as a share of the old price.
+    pub avg_price_cut: f64,
+}
+
+/// Time-on-market and price-cut figures per municipality.
+pub fn market_activity(listings: &[StoredListing], events: &[ListingEvent]) -> Vec<CityActivity> {
+    let mut city_of = BTreeMap::new();
+    let mut days: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut activity: BTreeMap<String, CityActivity> = BTreeMap::new();
+
+    for listing in listings {
+        let city = listing.property.municipality.clone();
+        city_of.insert(listing.property.property_code.clone(), city.clone());
+
+        let entry = activity.entry(city.clone()).or_insert_with(|| CityActivity {
+            city: city.clone(),
+            ..Default::default()
+        });
+        entry.listings += 1;
+        if listing.delisted_at.is_some() {
+            entry.delisted += 1;
+        }
+
+        let end = listing.delisted_at.as_deref().or(listing.last_seen.as_deref());
+        if let (Some(start), Some(end)) = (listing.first_seen.as_deref().and_then(parse_date), end.and_then(parse_date)) {
+            days.entry(city).or_default().push((end - start).num_seconds() as f64 / 86_400.0);
+        }
+    }
+
+    let mut cuts: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for event in events.iter().filter(|e| e.kind == EventKind::PriceChange) {
+        let (Some(old_price), Some(new_price)) = (event.old_price, event.new_price) else { continue };
+        let Some(city) = city_of.get(&event.property_code) else { continue };
+
+        if new_price < old_price && old_price > 0.0 {
+            cuts.entry(city.clone()).or_default().push((old_price - new_price) / old_price);
+        }
+    }
+
+    for (city, entry) in activity.iter_mut() {
+        if let Some(values) = days.get(city) {
+            entry.avg_days_on_market = mean(values);
+        }
+        if let Some(values) = cuts.get(city) {
+            entry.price_cuts = values.len();
+            entry.avg_price_cut = mean(values);
+        }
+    }
+
+    activity.into_values().collect()
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok().map(|d| d.with_timezone(&Utc))
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 }
+}
@@ -0,0 +1,442 @@
+use crate::core::encoding::LocationEncoder;
+use crate::core::features::Feature;
+use crate::core::prior::PricePrior;
+use crate::core::types::Property;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// What to do with a listing that has no value for a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingStrategy {
+    /// Leave the listing out of training. Predictions fall back to the median.
+    Drop,
+    Zero,
+    Median,
+    /// Most common value, for counts and flags.
+    Mode,
+    /// Median of the same neighbourhood, else district, else municipality, else everywhere.
+    Neighbourhood,
+    /// Median, plus a 0/1 column telling the model the value was missing.
+    Indicator,
+}
+
+impl MissingStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MissingStrategy::Drop => "drop",
+            MissingStrategy::Zero => "zero",
+            MissingStrategy::Median => "median",
+            MissingStrategy::Mode => "mode",
+            MissingStrategy::Neighbourhood => "neighbourhood",
+            MissingStrategy::Indicator => "indicator",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            MissingStrategy::Drop,
+            MissingStrategy::Zero,
+            MissingStrategy::Median,
+            MissingStrategy::Mode,
+            MissingStrategy::Neighbourhood,
+            MissingStrategy::Indicator,
+        ]
+        .into_iter()
+        .find(|strategy| strategy.name() == value)
+    }
+
+    /// Strategy used when `MISSING_VALUES` doesn't name the feature. Idealista leaves
+    /// out amenities a listing doesn't have, so missing flags count as "no"; the lift is
+    /// the exception, as it's missing for a quarter of the listings either way.
+    pub fn default_for(feature: Feature) -> Self {
+        match feature {
+            Feature::Size | Feature::PricePerSqm => MissingStrategy::Drop,
+            Feature::Latitude | Feature::Longitude => MissingStrategy::Neighbourhood,
+            Feature::Rooms | Feature::Bathrooms => MissingStrategy::Mode,
+            Feature::HasLift => MissingStrategy::Indicator,
+            Feature::EnergyRating | Feature::Photos | Feature::AreaPricePerSqm => MissingStrategy::Median,
+            _ => MissingStrategy::Zero,
+        }
+    }
+}
+
+/// Per-feature strategies from `MISSING_VALUES`, e.g. `latitude=drop,has_lift=mode`.
+pub fn strategies_from_env(features: &[Feature]) -> Result<Vec<MissingStrategy>, Box<dyn Error>> {
+    let mut overrides = HashMap::new();
+    let configured = std::env::var("MISSING_VALUES").unwrap_or_default();
+
+    for entry in configured.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (name, strategy) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Expected feature=strategy in MISSING_VALUES, got {}", entry))?;
+        let feature = Feature::parse(name.trim()).ok_or_else(|| format!("Unknown model feature {}", name))?;
+        let strategy = MissingStrategy::parse(strategy.trim())
+            .ok_or_else(|| format!("Unknown missing-value strategy {}", strategy))?;
+        overrides.insert(feature.name(), strategy);
+    }
+
+    Ok(features
+        .iter()
+        .map(|feature| overrides.get(feature.name()).copied().unwrap_or(MissingStrategy::default_for(*feature)))
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ColumnImputer {
+    feature: Feature,
+    strategy: MissingStrategy,
+    /// Value used when nothing more specific applies.
+    fill: f64,
+    /// Medians per area key, only for `Neighbourhood`.
+    by_area: HashMap<String, f64>,
+}
+
+/// Turns listings into model rows, filling missing values the way it was fitted to.
+/// Saved with the model so predictions are filled like the training data was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Imputer {
+    columns: Vec<ColumnImputer>,
+    /// Fitted when the features include `AreaPricePerSqm`.
+    prior: Option<PricePrior>,
+    /// Extra columns encoding the location, when `LOCATION_ENCODING` is set.
+    location: Option<LocationEncoder>,
+}
+
+/// How many training listings each feature's strategy touched.
+#[derive(Debug, Default)]
+pub struct ImputationReport {
+    pub total_rows: usize,
+    pub dropped_rows: usize,
+    /// (feature, strategy, listings missing the feature)
+    pub missing: Vec<(Feature, MissingStrategy, usize)>,
+}
+
+impl Imputer {
+    /// Learns fill values for each feature from the listings that have it.
+    pub fn fit(
+        features: &[Feature],
+        strategies: &[MissingStrategy],
+        location: Option<LocationEncoder>,
+        properties: &[Property],
+    ) -> Self {
+        let prior = features
+            .contains(&Feature::AreaPricePerSqm)
+            .then(|| PricePrior::fit(properties));
+
+        let columns = features
+            .iter()
+            .zip(strategies)
+            .map(|(&feature, &strategy)| {
+                let observed: Vec<f64> = properties
+                    .iter()
+                    .filter_map(|p| feature_value(feature, p, prior.as_ref(), true))
+                    .collect();
+                let fill = match strategy {
+                    MissingStrategy::Zero => 0.0,
+                    MissingStrategy::Mode => mode(&observed).unwrap_or(0.0),
+                    _ => median(observed).unwrap_or(0.0),
+                };
+                let by_area = if strategy == MissingStrategy::Neighbourhood {
+                    area_medians(feature, properties)
+                } else {
+                    HashMap::new()
+                };
+                ColumnImputer { feature, strategy, fill, by_area }
+            })
+            .collect();
+
+        Self { columns, prior, location }
+    }
+
+    pub fn features(&self) -> Vec<Feature> {
+        self.columns.iter().map(|column| column.feature).collect()
+    }
+
+    /// Column names of a row: the features, an indicator per `Indicator` feature, then
+    /// the location encoding.
+    pub fn column_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.columns.iter().map(|c| c.feature.name().to_string()).collect();
+        for column in self.columns.iter().filter(|c| c.strategy == MissingStrategy::Indicator) {
+            names.push(format!("{}_missing", column.feature.name()));
+        }
+        if let Some(location) = &self.location {
+            names.extend(location.column_names());
+        }
+        names
+    }
+
+    pub fn location(&self) -> Option<&LocationEncoder> {
+        self.location.as_ref()
+    }
+
+    /// Model row for a listing to predict. `Drop` features fall back to their median.
+    pub fn row(&self, property: &Property) -> Vec<f64> {
+        self.build_row(property, false)
+    }
+
+    /// `training` rows get the out-of-fold price prior and location encoding of the listing.
+    fn build_row(&self, property: &Property, training: bool) -> Vec<f64> {
+        let mut row = Vec::with_capacity(self.columns.len());
+        let mut indicators = Vec::new();
+
+        for column in &self.columns {
+            let value = feature_value(column.feature, property, self.prior.as_ref(), training);
+            row.push(value.unwrap_or_else(|| column.fill_for(property)));
+            if column.strategy == MissingStrategy::Indicator {
+                indicators.push(if value.is_none() { 1.0 } else { 0.0 });
+            }
+        }
+
+        row.extend(indicators);
+        if let Some(location) = &self.location {
+            row.extend(location.encode(property, training));
+        }
+        row
+    }
+
+    /// Rows for training, leaving out listings missing a `Drop` feature. Returns the
+    /// rows, the index of the listing each came from and what was imputed.
+    pub fn training_rows(&self, properties: &[Property]) -> (Vec<Vec<f64>>, Vec<usize>, ImputationReport) {
+        self.rows(properties, true)
+    }
+
+    /// Rows for listings held out of training, built like prediction rows but leaving out
+    /// the same listings `training_rows` would, so they're scored on what the model trains on.
+    pub fn holdout_rows(&self, properties: &[Property]) -> (Vec<Vec<f64>>, Vec<usize>, ImputationReport) {
+        self.rows(properties, false)
+    }
+
+    fn rows(&self, properties: &[Property], training: bool) -> (Vec<Vec<f64>>, Vec<usize>, ImputationReport) {
+        let mut report = ImputationReport {
+            total_rows: properties.len(),
+            dropped_rows: 0,
+            missing: self.columns.iter().map(|c| (c.feature, c.strategy, 0)).collect(),
+        };
+        let mut rows = Vec::new();
+        let mut kept = Vec::new();
+
+        for (i, property) in properties.iter().enumerate() {
+            let mut dropped = false;
+            for (column, (_, _, count)) in self.columns.iter().zip(report.missing.iter_mut()) {
+                if feature_value(column.feature, property, self.prior.as_ref(), training).is_none() {
+                    *count += 1;
+                    dropped |= column.strategy == MissingStrategy::Drop;
+                }
+            }
+
+            if dropped {
+                report.dropped_rows += 1;
+            } else {
+                rows.push(self.build_row(property, training));
+                kept.push(i);
+            }
+        }
+
+        (rows, kept, report)
+    }
+}
+
+impl ColumnImputer {
+    fn fill_for(&self, property: &Property) -> f64 {
+        if self.strategy == MissingStrategy::Neighbourhood {
+            for key in area_keys(property) {
+                if let Some(value) = self.by_area.get(&key) {
+                    return *value;
+                }
+            }
+        }
+        self.fill
+    }
+}
+
+impl fmt::Display for ImputationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Missing values in {} listings ({} dropped):", self.total_rows, self.dropped_rows)?;
+        for (feature, strategy, count) in self.missing.iter().filter(|(_, _, count)| *count > 0) {
+            writeln!(f, "  {}: {} missing, {}", feature.name(), count, strategy.name())?;
+        }
+        Ok(())
+    }
+}
+
+fn feature_value(feature: Feature, property: &Property, prior: Option<&PricePrior>, training: bool) -> Option<f64> {
+    match (feature, prior) {
+        (Feature::AreaPricePerSqm, Some(prior)) if training => prior.estimate_out_of_fold(property),
+        (Feature::AreaPricePerSqm, Some(prior)) => prior.estimate(property),
+        _ => feature.value(property),
+    }
+}
+
+/// Most specific first: neighbourhood, district, municipality.
+pub(crate) fn area_keys(property: &Property) -> Vec<String> {
+    let mut keys = Vec::new();
+    if !property.municipality.is_empty() {
+        if !property.district.is_empty() {
+            if !property.neighborhood.is_empty() {
+                keys.push(format!("n|{}|{}|{}", property.municipality, property.district, property.neighborhood));
+            }
+            keys.push(format!("d|{}|{}", property.municipality, property.district));
+        }
+        keys.push(format!("m|{}", property.municipality));
+    }
+    keys
+}
+
+fn area_medians(feature: Feature, properties: &[Property]) -> HashMap<String, f64> {
+    let mut values: HashMap<String, Vec<f64>> = HashMap::new();
+    for property in properties {
+        if let Some(value) = feature.value(property) {
+            for key in area_keys(property) {
+                values.entry(key).or_default().push(value);
+            }
+        }
+    }
+
+    values
+        .into_iter()
+        .filter_map(|(key, values)| median(values).map(|median| (key, median)))
+        .collect()
+}
+
+pub(crate) fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] })
+}
+
+fn mode(values: &[f64]) -> Option<f64> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value.to_bits()).or_default() += 1;
+    }
+    // Ties go to the smaller value so the result doesn't depend on hash order.
+    counts
+        .into_iter()
+        .max_by(|(a, count_a), (b, count_b)| count_a.cmp(count_b).then(f64::from_bits(*b).total_cmp(&f64::from_bits(*a))))
+        .map(|(bits, _)| f64::from_bits(bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{Floor, PropertyType};
+
+    /// A home in the given area with every optional field missing.
+    fn listing(municipality: &str, district: &str, neighborhood: &str) -> Property {
+        Property {
+            property_code: String::new(),
+            price_eur: 200_000.0,
+            size_sqm: None,
+            floor: Floor::Unknown,
+            address: String::new(),
+            province: String::new(),
+            municipality: municipality.to_string(),
+            district: district.to_string(),
+            neighborhood: neighborhood.to_string(),
+            latitude: None,
+            longitude: None,
+            has_lift: None,
+            price_per_sqm: None,
+            rooms: None,
+            bathrooms: None,
+            swimming_pool: None,
+            garden: None,
+            garage: None,
+            url: String::new(),
+            property_type: PropertyType::Homes,
+            status: None,
+            exterior: None,
+            energy_rating: None,
+            num_photos: None,
+            description: None,
+            has_parking_space: None,
+            parking_included: None,
+            new_development: None,
+            sub_typology: None,
+        }
+    }
+
+    fn sized(size: Option<f64>, rooms: Option<u32>) -> Property {
+        Property { size_sqm: size, rooms, ..listing("Zaragoza", "", "") }
+    }
+
+    #[test]
+    fn drops_listings_missing_a_drop_feature_and_counts_every_gap() {
+        let properties = [
+            sized(Some(80.0), Some(3)),
+            sized(None, Some(2)),
+            sized(Some(60.0), None),
+            sized(None, None),
+        ];
+        let imputer = Imputer::fit(&[Feature::Size, Feature::Rooms], &[MissingStrategy::Drop, MissingStrategy::Mode], None, &properties);
+
+        let (rows, kept, report) = imputer.training_rows(&properties);
+
+        assert_eq!(kept, vec![0, 2]);
+        assert_eq!(rows, vec![vec![80.0, 3.0], vec![60.0, 2.0]]);
+        assert_eq!((report.total_rows, report.dropped_rows), (4, 2));
+        assert_eq!(
+            report.missing,
+            vec![(Feature::Size, MissingStrategy::Drop, 2), (Feature::Rooms, MissingStrategy::Mode, 2)]
+        );
+        // A listing to predict isn't dropped; its size falls back to the median.
+        assert_eq!(imputer.row(&sized(None, Some(4))), vec![70.0, 4.0]);
+    }
+
+    #[test]
+    fn mode_ties_go_to_the_smaller_value() {
+        let mut properties: Vec<Property> = [3, 1, 2, 3, 2].into_iter().map(|rooms| sized(Some(70.0), Some(rooms))).collect();
+        properties.push(sized(Some(70.0), None));
+
+        let imputer = Imputer::fit(&[Feature::Rooms], &[MissingStrategy::Mode], None, &properties);
+
+        assert_eq!(imputer.row(&sized(None, None)), vec![2.0]);
+    }
+
+    #[test]
+    fn neighbourhood_falls_back_to_district_municipality_then_everywhere() {
+        let located = |latitude: f64, municipality, district, neighborhood| Property {
+            latitude: Some(latitude),
+            ..listing(municipality, district, neighborhood)
+        };
+        let properties = [
+            located(10.0, "Zaragoza", "Centro", "Pilar"),
+            located(20.0, "Zaragoza", "Centro", "Magdalena"),
+            located(40.0, "Zaragoza", "Delicias", ""),
+            located(100.0, "Burgos", "", ""),
+        ];
+        let imputer = Imputer::fit(&[Feature::Latitude], &[MissingStrategy::Neighbourhood], None, &properties);
+
+        let fill = |municipality, district, neighborhood| imputer.row(&listing(municipality, district, neighborhood))[0];
+        assert_eq!(fill("Zaragoza", "Centro", "Pilar"), 10.0);
+        assert_eq!(fill("Zaragoza", "Centro", "San Pablo"), 15.0);
+        assert_eq!(fill("Zaragoza", "Actur", ""), 20.0);
+        assert_eq!(fill("Madrid", "Centro", "Pilar"), 30.0);
+    }
+
+    #[test]
+    fn indicator_features_get_a_missing_column_after_the_features() {
+        let with_lift = |has_lift| Property { has_lift, ..sized(Some(80.0), None) };
+        let properties = [with_lift(Some(true)), with_lift(Some(true)), with_lift(Some(false)), with_lift(None)];
+        let imputer = Imputer::fit(
+            &[Feature::HasLift, Feature::Rooms],
+            &[MissingStrategy::Indicator, MissingStrategy::Zero],
+            None,
+            &properties,
+        );
+
+        assert_eq!(imputer.column_names(), vec!["has_lift", "rooms", "has_lift_missing"]);
+        let (rows, _, report) = imputer.training_rows(&properties);
+        assert_eq!(rows[2], vec![0.0, 0.0, 0.0]);
+        assert_eq!(rows[3], vec![1.0, 0.0, 1.0], "missing lift is filled with the median and flagged");
+        assert_eq!(report.dropped_rows, 0);
+        assert_eq!(report.to_string(), "Missing values in 4 listings (0 dropped):\n  has_lift: 1 missing, indicator\n  rooms: 4 missing, zero\n");
+    }
+}
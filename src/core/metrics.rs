@@ -0,0 +1,141 @@
+use ndarray::{ArrayView1, Zip};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Score used to compare hyperparameters in cross-validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    R2,
+    Rmse,
+    Mae,
+    /// Mean absolute percentage error, as a fraction (0.12 = 12%).
+    Mape,
+}
+
+impl Metric {
+    /// Reads `MODEL_METRIC` (`r2`, `rmse`, `mae` or `mape`), defaulting to R².
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("MODEL_METRIC").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "r2" => Ok(Metric::R2),
+            "rmse" => Ok(Metric::Rmse),
+            "mae" => Ok(Metric::Mae),
+            "mape" => Ok(Metric::Mape),
+            other => Err(format!("Unknown MODEL_METRIC {}, expected r2, rmse, mae or mape", other).into()),
+        }
+    }
+
+    /// Key stored with model runs.
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            Metric::R2 => "r2",
+            Metric::Rmse => "rmse",
+            Metric::Mae => "mae",
+            Metric::Mape => "mape",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::R2 => "R²",
+            Metric::Rmse => "RMSE",
+            Metric::Mae => "MAE",
+            Metric::Mape => "MAPE",
+        }
+    }
+
+    /// R² grows with a better fit, the error metrics shrink.
+    pub fn higher_is_better(&self) -> bool {
+        *self == Metric::R2
+    }
+
+    /// Whether `score` beats `best`.
+    pub fn is_better(&self, score: f64, best: f64) -> bool {
+        if self.higher_is_better() { score > best } else { score < best }
+    }
+
+    /// Starting point for a search, beaten by any real score.
+    pub fn worst(&self) -> f64 {
+        if self.higher_is_better() { f64::NEG_INFINITY } else { f64::INFINITY }
+    }
+
+    pub fn compute(&self, predicted: ArrayView1<f64>, expected: ArrayView1<f64>) -> f64 {
+        let n = expected.len() as f64;
+        match self {
+            Metric::R2 => {
+                let mean = expected.mean().unwrap_or(0.0);
+                let residual: f64 = Zip::from(predicted).and(expected).fold(0.0, |acc, p, e| acc + (e - p).powi(2));
+                let total: f64 = expected.iter().map(|e| (e - mean).powi(2)).sum();
+                if total == 0.0 { 0.0 } else { 1.0 - residual / total }
+            }
+            Metric::Rmse => {
+                let squared: f64 = Zip::from(predicted).and(expected).fold(0.0, |acc, p, e| acc + (e - p).powi(2));
+                (squared / n).sqrt()
+            }
+            Metric::Mae => Zip::from(predicted).and(expected).fold(0.0, |acc, p, e| acc + (e - p).abs()) / n,
+            Metric::Mape => {
+                let (sum, count) = Zip::from(predicted)
+                    .and(expected)
+                    .fold((0.0, 0usize), |(sum, count), p, e| {
+                        if *e == 0.0 { (sum, count) } else { (sum + ((e - p) / e).abs(), count + 1) }
+                    });
+                if count == 0 { 0.0 } else { sum / count as f64 }
+            }
+        }
+    }
+
+    /// `score` formatted for the console: euros for the error metrics, a percentage for MAPE.
+    pub fn format(&self, score: f64) -> String {
+        match self {
+            Metric::R2 => format!("{} {:.4}", self.name(), score),
+            Metric::Rmse | Metric::Mae => format!("{} €{:.2}", self.name(), score),
+            Metric::Mape => format!("{} {:.2}%", self.name(), score * 100.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    const ALL: [Metric; 4] = [Metric::R2, Metric::Rmse, Metric::Mae, Metric::Mape];
+
+    #[test]
+    fn r2_prefers_the_higher_score() {
+        assert!(Metric::R2.is_better(0.8, 0.6));
+        assert!(!Metric::R2.is_better(0.6, 0.8));
+    }
+
+    #[test]
+    fn error_metrics_prefer_the_lower_score() {
+        for metric in [Metric::Rmse, Metric::Mae, Metric::Mape] {
+            assert!(metric.is_better(1_000.0, 2_000.0), "{:?}", metric);
+            assert!(!metric.is_better(2_000.0, 1_000.0), "{:?}", metric);
+        }
+    }
+
+    #[test]
+    fn worst_loses_to_any_finite_score() {
+        for metric in ALL {
+            for score in [-1e12, -1.0, 0.0, 0.5, 1.0, 1e12] {
+                assert!(metric.is_better(score, metric.worst()), "{:?} {}", metric, score);
+            }
+        }
+    }
+
+    #[test]
+    fn computes_each_metric() {
+        let expected = array![100.0, 200.0, 300.0];
+        let predicted = array![110.0, 190.0, 300.0];
+
+        let r2 = Metric::R2.compute(predicted.view(), expected.view());
+        assert!((r2 - (1.0 - 200.0 / 20_000.0)).abs() < 1e-12);
+        let rmse = Metric::Rmse.compute(predicted.view(), expected.view());
+        assert!((rmse - (200.0_f64 / 3.0).sqrt()).abs() < 1e-12);
+        let mae = Metric::Mae.compute(predicted.view(), expected.view());
+        assert!((mae - 20.0 / 3.0).abs() < 1e-12);
+        let mape = Metric::Mape.compute(predicted.view(), expected.view());
+        assert!((mape - (0.1 + 0.05) / 3.0).abs() < 1e-12);
+    }
+}
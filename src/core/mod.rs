@@ -1,3 +1,15 @@
+pub mod boosting;
 pub mod cervo;
+pub mod config;
+pub mod dataset;
+pub mod encoding;
+pub mod features;
+pub mod history;
+pub mod imputation;
+pub mod metrics;
+pub mod preprocessing;
+pub mod prior;
 pub mod types;
-pub mod scrapers;
\ No newline at end of file
+pub mod scrapers;
+pub mod storage;
+pub mod yields;
\ No newline at end of file
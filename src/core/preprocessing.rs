@@ -0,0 +1,251 @@
+use crate::core::features::Feature;
+use crate::core::imputation;
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Right-skewed features log transformed unless `LOG_FEATURES` says otherwise.
+pub const DEFAULT_LOG_FEATURES: [Feature; 3] = [Feature::Size, Feature::AreaPricePerSqm, Feature::PricePerSqm];
+
+/// How each column is rescaled after the log transforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    /// Raw values.
+    None,
+    /// Subtract the mean, divide by the standard deviation.
+    Standard,
+    /// Subtract the median, divide by the interquartile range. Less affected by outliers.
+    Robust,
+}
+
+impl Scaling {
+    /// Reads `FEATURE_SCALING` (`standard`, `robust` or `none`), defaulting to standard.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("FEATURE_SCALING").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "standard" => Ok(Scaling::Standard),
+            "robust" => Ok(Scaling::Robust),
+            "none" => Ok(Scaling::None),
+            other => Err(format!("Unknown FEATURE_SCALING {}, expected standard, robust or none", other).into()),
+        }
+    }
+}
+
+/// Features listed in `LOG_FEATURES` (comma separated, or `none`), defaulting to
+/// `DEFAULT_LOG_FEATURES`. Features the model doesn't use are ignored.
+pub fn log_features_from_env() -> Result<Vec<Feature>, Box<dyn Error>> {
+    let configured = match std::env::var("LOG_FEATURES") {
+        Ok(value) => value,
+        Err(_) => return Ok(DEFAULT_LOG_FEATURES.to_vec()),
+    };
+
+    configured
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && *name != "none")
+        .map(|name| Feature::parse(name).ok_or_else(|| format!("Unknown model feature {} in LOG_FEATURES", name).into()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ColumnTransform {
+    name: String,
+    /// Applies `ln(1 + x)` before scaling. Negative values are clamped to 0.
+    log: bool,
+    center: f64,
+    scale: f64,
+}
+
+/// Transforms imputed rows into what the model was trained on. Fitted on the training
+/// rows and saved with the model, so predictions are transformed the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    scaling: Scaling,
+    columns: Vec<ColumnTransform>,
+}
+
+impl Pipeline {
+    /// Fits one transform per column of `rows`, named by `column_names`.
+    pub fn fit(column_names: &[String], rows: &[Vec<f64>], scaling: Scaling, log_features: &[Feature]) -> Self {
+        let columns = column_names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let log = log_features.iter().any(|feature| feature.name() == name);
+                let values: Vec<f64> = rows.iter().map(|row| log_value(row[i], log)).collect();
+                let (center, scale) = match scaling {
+                    Scaling::None => (0.0, 1.0),
+                    Scaling::Standard => mean_and_std(&values),
+                    Scaling::Robust => median_and_iqr(values),
+                };
+                ColumnTransform { name: name.clone(), log, center, scale }
+            })
+            .collect();
+
+        Self { scaling, columns }
+    }
+
+    pub fn transform(&self, row: &[f64]) -> Vec<f64> {
+        row.iter()
+            .zip(&self.columns)
+            .map(|(value, column)| (log_value(*value, column.log) - column.center) / column.scale)
+            .collect()
+    }
+
+    /// Scaling and log transformed columns, for the console.
+    pub fn describe(&self) -> String {
+        let logged: Vec<&str> = self.columns.iter().filter(|c| c.log).map(|c| c.name.as_str()).collect();
+        let logged = if logged.is_empty() { "none".to_string() } else { logged.join(", ") };
+        format!("{:?} scaling, log transformed: {}", self.scaling, logged)
+    }
+}
+
+fn log_value(value: f64, log: bool) -> f64 {
+    if log { value.max(0.0).ln_1p() } else { value }
+}
+
+/// Constant columns keep a scale of 1 so they stay finite.
+fn nonzero(scale: f64) -> f64 {
+    if scale > f64::EPSILON && scale.is_finite() { scale } else { 1.0 }
+}
+
+fn mean_and_std(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 1.0);
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, nonzero(variance.sqrt()))
+}
+
+fn median_and_iqr(mut values: Vec<f64>) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 1.0);
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let quantile = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    let iqr = quantile(0.75) - quantile(0.25);
+    (imputation::median(values.clone()).unwrap_or(0.0), nonzero(iqr))
+}
+
+/// What the model regresses on. Predictions are always converted back to euros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Price,
+    /// ln(price), for right-skewed prices.
+    LogPrice,
+    /// ln(price / size), multiplied back by the size.
+    LogPricePerSqm,
+}
+
+impl Target {
+    /// Reads `MODEL_TARGET` (`price`, `log_price` or `log_price_per_sqm`), defaulting to price.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("MODEL_TARGET").unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "price" => Ok(Target::Price),
+            "log_price" => Ok(Target::LogPrice),
+            "log_price_per_sqm" => Ok(Target::LogPricePerSqm),
+            other => Err(format!("Unknown MODEL_TARGET {}, expected price, log_price or log_price_per_sqm", other).into()),
+        }
+    }
+
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            Target::Price => "price",
+            Target::LogPrice => "log_price",
+            Target::LogPricePerSqm => "log_price_per_sqm",
+        }
+    }
+
+    /// Target value of a listing, `None` when the log is undefined (no price or size).
+    pub fn value(&self, price: f64, size: Option<f64>) -> Option<f64> {
+        match self {
+            Target::Price => Some(price),
+            Target::LogPrice => (price > 0.0).then(|| price.ln()),
+            Target::LogPricePerSqm => size.filter(|size| *size > 0.0 && price > 0.0).map(|size| (price / size).ln()),
+        }
+    }
+}
+
+/// Converts model outputs back to euros. `exp` of a mean log is the median, not the mean,
+/// so log targets are scaled by Duan's smearing factor: the mean of `exp(residual)` over
+/// the training rows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetTransform {
+    target: Target,
+    smearing: f64,
+}
+
+impl TargetTransform {
+    /// Fits the smearing factor from the model's `predicted` targets for the training rows.
+    pub fn fit(target: Target, predicted: &[f64], actual: &[f64]) -> Self {
+        let smearing = match target {
+            Target::Price => 1.0,
+            _ if actual.is_empty() => 1.0,
+            _ => {
+                let sum: f64 = predicted.iter().zip(actual).map(|(p, a)| (a - p).exp()).sum();
+                sum / actual.len() as f64
+            }
+        };
+        Self { target, smearing }
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Price in euros for a model output. `size` is only used by per-m² targets.
+    pub fn price(&self, prediction: f64, size: f64) -> f64 {
+        match self.target {
+            Target::Price => prediction,
+            Target::LogPrice => prediction.exp() * self.smearing,
+            Target::LogPricePerSqm => prediction.exp() * self.smearing * size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn log_targets_round_trip_with_zero_residuals() {
+        let listings = [(150_000.0, 75.0), (320_000.0, 110.0), (89_000.0, 48.0)];
+
+        for target in [Target::LogPrice, Target::LogPricePerSqm] {
+            let values: Vec<f64> = listings.iter().map(|&(price, size)| target.value(price, Some(size)).unwrap()).collect();
+            let transform = TargetTransform::fit(target, &values, &values);
+            assert!(close(transform.smearing, 1.0), "{:?}", target);
+
+            for (&(price, size), value) in listings.iter().zip(&values) {
+                assert!(close(transform.price(*value, size), price), "{:?} {}", target, price);
+            }
+        }
+    }
+
+    #[test]
+    fn smearing_is_the_mean_exponentiated_residual() {
+        let predicted = [0.0, 0.0];
+        let actual = [2.0_f64.ln(), 4.0_f64.ln()];
+
+        let transform = TargetTransform::fit(Target::LogPrice, &predicted, &actual);
+
+        // (e^ln2 + e^ln4) / 2 = (2 + 4) / 2
+        assert!(close(transform.smearing, 3.0));
+        assert!(close(transform.price(10.0_f64.ln(), 80.0), 30.0));
+        let per_sqm = TargetTransform::fit(Target::LogPricePerSqm, &predicted, &actual);
+        assert!(close(per_sqm.price(10.0_f64.ln(), 80.0), 2_400.0));
+    }
+
+    #[test]
+    fn price_target_is_not_smeared() {
+        let transform = TargetTransform::fit(Target::Price, &[100.0], &[150.0]);
+        assert_eq!(transform.price(123.0, 50.0), 123.0);
+    }
+}
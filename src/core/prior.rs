@@ -0,0 +1,176 @@
+use crate::core::imputation::{self, area_keys};
+use crate::core::types::Property;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// An area is only trusted with at least this many listings; smaller ones fall back to the
+/// enclosing district, municipality or the whole dataset.
+const MIN_AREA_LISTINGS: usize = 3;
+/// Folds for the out-of-fold priors of training listings.
+const PRIOR_FOLDS: usize = 5;
+
+/// Typical price per m² around a listing: the median of the listings in its neighbourhood,
+/// district or municipality. It stands in for the listing's own price per m², which is
+/// the target divided by the size and so can't be a feature.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PricePrior {
+    /// (median, listings) per area key, `""` for the whole dataset.
+    medians: HashMap<String, (f64, usize)>,
+    /// Priors of the training listings by property code, each from the medians of the other
+    /// folds so the listing's own price can't leak into its feature. Only needed while
+    /// training, so not saved with the model.
+    #[serde(skip)]
+    out_of_fold: HashMap<String, Option<f64>>,
+}
+
+impl PricePrior {
+    pub fn fit(properties: &[Property]) -> Self {
+        let mut prior = Self { medians: area_medians(properties.iter()), out_of_fold: HashMap::new() };
+
+        for fold in 0..PRIOR_FOLDS {
+            let others = Self {
+                medians: area_medians(properties.iter().enumerate().filter(|(i, _)| i % PRIOR_FOLDS != fold).map(|(_, p)| p)),
+                out_of_fold: HashMap::new(),
+            };
+            for property in properties.iter().skip(fold).step_by(PRIOR_FOLDS) {
+                prior.out_of_fold.insert(property.property_code.clone(), others.estimate(property));
+            }
+        }
+
+        prior
+    }
+
+    /// Prior for a listing that wasn't part of training.
+    pub fn estimate(&self, property: &Property) -> Option<f64> {
+        area_keys(property)
+            .into_iter()
+            .chain([String::new()])
+            .find_map(|key| match self.medians.get(&key) {
+                Some(&(median, listings)) if listings >= MIN_AREA_LISTINGS => Some(median),
+                _ => None,
+            })
+    }
+
+    /// Prior for a training listing, from the listings outside its fold.
+    pub fn estimate_out_of_fold(&self, property: &Property) -> Option<f64> {
+        match self.out_of_fold.get(&property.property_code) {
+            Some(estimate) => *estimate,
+            None => self.estimate(property),
+        }
+    }
+}
+
+/// (median, listings) of the price per m² per area key of `properties`.
+fn area_medians<'a>(properties: impl Iterator<Item = &'a Property>) -> HashMap<String, (f64, usize)> {
+    let mut values: HashMap<String, Vec<f64>> = HashMap::new();
+    for property in properties {
+        if let Some(price_per_sqm) = price_per_sqm(property) {
+            for key in area_keys(property).into_iter().chain([String::new()]) {
+                values.entry(key).or_default().push(price_per_sqm);
+            }
+        }
+    }
+
+    values
+        .into_iter()
+        .filter_map(|(key, values)| {
+            let listings = values.len();
+            imputation::median(values).map(|median| (key, (median, listings)))
+        })
+        .collect()
+}
+
+/// The listing's price per m², from Idealista's `priceByArea` or price / size.
+fn price_per_sqm(property: &Property) -> Option<f64> {
+    property
+        .price_per_sqm
+        .or_else(|| property.size_sqm.filter(|size| *size > 0.0).map(|size| property.price_eur / size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{Floor, PropertyType};
+
+    fn listing(code: usize, price_per_sqm: f64, neighborhood: &str) -> Property {
+        Property {
+            property_code: code.to_string(),
+            price_eur: price_per_sqm * 100.0,
+            size_sqm: Some(100.0),
+            floor: Floor::Unknown,
+            address: String::new(),
+            province: String::new(),
+            municipality: "Zaragoza".to_string(),
+            district: "Centro".to_string(),
+            neighborhood: neighborhood.to_string(),
+            latitude: None,
+            longitude: None,
+            has_lift: None,
+            price_per_sqm: None,
+            rooms: None,
+            bathrooms: None,
+            swimming_pool: None,
+            garden: None,
+            garage: None,
+            url: String::new(),
+            property_type: PropertyType::Homes,
+            status: None,
+            exterior: None,
+            energy_rating: None,
+            num_photos: None,
+            description: None,
+            has_parking_space: None,
+            parking_included: None,
+            new_development: None,
+            sub_typology: None,
+        }
+    }
+
+    fn listings(prices_per_sqm: &[f64]) -> Vec<Property> {
+        prices_per_sqm.iter().enumerate().map(|(i, price)| listing(i, *price, "Pilar")).collect()
+    }
+
+    #[test]
+    fn training_prior_does_not_depend_on_the_listing_own_price() {
+        let prices: Vec<f64> = (1..=15).map(|i| i as f64 * 100.0).collect();
+        let mut properties = listings(&prices);
+        let before: Vec<Option<f64>> = {
+            let prior = PricePrior::fit(&properties);
+            properties.iter().map(|p| prior.estimate_out_of_fold(p)).collect()
+        };
+
+        for (i, own) in [(0, 50_000.0), (7, 1.0), (14, 800.0)] {
+            properties[i].price_eur = own * 100.0;
+            let prior = PricePrior::fit(&properties);
+            assert_eq!(prior.estimate_out_of_fold(&properties[i]), before[i], "listing {}", i);
+            properties[i].price_eur = prices[i] * 100.0;
+        }
+    }
+
+    #[test]
+    fn training_prior_comes_from_the_other_folds() {
+        let properties = listings(&(1..=10).map(|i| i as f64).collect::<Vec<_>>());
+        let prior = PricePrior::fit(&properties);
+
+        // Listing 0 shares its fold with listing 5, so its prior is the median of the other
+        // eight: 2, 3, 4, 5, 7, 8, 9 and 10.
+        assert_eq!(prior.estimate_out_of_fold(&properties[0]), Some(6.0));
+        assert_eq!(prior.estimate_out_of_fold(&properties[5]), Some(6.0));
+        assert_eq!(prior.estimate_out_of_fold(&properties[4]), Some(5.0));
+        assert_eq!(prior.estimate_out_of_fold(&properties[2]), Some(5.5));
+    }
+
+    #[test]
+    fn predictions_use_every_training_listing_and_fall_back_from_small_areas() {
+        let mut properties = listings(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        properties.push(listing(5, 100.0, "Magdalena"));
+        let prior = PricePrior::fit(&properties);
+
+        assert_eq!(prior.estimate(&listing(99, 0.0, "Pilar")), Some(3.0));
+        // One listing isn't enough for Magdalena, so it takes the district's median.
+        assert_eq!(prior.estimate(&listing(99, 0.0, "Magdalena")), Some(3.5));
+        // A listing the prior wasn't fitted on gets the full-data estimate in training too.
+        assert_eq!(prior.estimate_out_of_fold(&listing(99, 0.0, "Pilar")), Some(3.0));
+    }
+}
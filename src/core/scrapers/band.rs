@@ -0,0 +1,173 @@
+use crate::core::types::Operation;
+
+use serde::Deserialize;
+
+/// What a truncated search is split on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitDimension {
+    Price,
+    Size,
+}
+
+impl SplitDimension {
+    fn params(&self) -> (&'static str, &'static str) {
+        match self {
+            SplitDimension::Price => ("minPrice", "maxPrice"),
+            SplitDimension::Size => ("minSize", "maxSize"),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SplitDimension::Price => "price",
+            SplitDimension::Size => "size",
+        }
+    }
+
+    /// Where an unbounded search is first cut in two.
+    fn initial_pivot(&self, operation: Operation) -> f64 {
+        match (self, operation) {
+            (SplitDimension::Price, Operation::Sale) => 250_000.0,
+            (SplitDimension::Price, Operation::Rent) => 1_200.0,
+            (SplitDimension::Size, _) => 100.0,
+        }
+    }
+}
+
+/// A slice of a location's search, e.g. homes priced 250000-500000. The root band
+/// has no bounds and is the plain search. Pivots are deterministic, so an interrupted
+/// run splits the same way again and its checkpoints and recordings still line up.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchBand {
+    pub dimension: SplitDimension,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl SearchBand {
+    pub fn root(dimension: SplitDimension) -> Self {
+        Self { dimension, min: None, max: None }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// Query string fragment restricting a `listhomes` search to this band.
+    pub fn query(&self) -> String {
+        let (min_param, max_param) = self.dimension.params();
+        let mut query = String::new();
+        if let Some(min) = self.min {
+            query.push_str(&format!("&{}={:.0}", min_param, min));
+        }
+        if let Some(max) = self.max {
+            query.push_str(&format!("&{}={:.0}", max_param, max));
+        }
+        query
+    }
+
+    /// Identifies the band in recordings and checkpoints; empty for the root band.
+    pub fn key(&self) -> String {
+        if self.is_root() {
+            return String::new();
+        }
+
+        let bound = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.0}", v));
+        format!("{}-{}-{}", self.dimension.name(), bound(self.min), bound(self.max))
+    }
+
+    /// Splits the band in two, or `None` once it can't be narrowed any further.
+    pub fn split(&self, operation: Operation) -> Option<(SearchBand, SearchBand)> {
+        let pivot = match (self.min, self.max) {
+            (None, None) => self.dimension.initial_pivot(operation),
+            (Some(min), None) => min * 2.0,
+            (None, Some(max)) => max / 2.0,
+            (Some(min), Some(max)) => (min + max) / 2.0,
+        }
+        .round();
+
+        let lower = self.min.unwrap_or(0.0);
+        let narrows = pivot > lower && self.max.is_none_or(|max| pivot < max);
+        if !narrows {
+            return None;
+        }
+
+        Some((
+            SearchBand { max: Some(pivot), ..*self },
+            SearchBand { min: Some(pivot), ..*self },
+        ))
+    }
+
+    /// Rebuilds a band from `listhomes` query parameters, for serving recordings.
+    pub fn from_params(param: impl Fn(&str) -> Option<f64>) -> Self {
+        for dimension in [SplitDimension::Price, SplitDimension::Size] {
+            let (min_param, max_param) = dimension.params();
+            let band = SearchBand { dimension, min: param(min_param), max: param(max_param) };
+            if !band.is_root() {
+                return band;
+            }
+        }
+        SearchBand::root(SplitDimension::Price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn band(dimension: SplitDimension, min: Option<f64>, max: Option<f64>) -> SearchBand {
+        SearchBand { dimension, min, max }
+    }
+
+    type Bounds = (Option<f64>, Option<f64>);
+
+    fn bounds(halves: Option<(SearchBand, SearchBand)>) -> Option<(Bounds, Bounds)> {
+        halves.map(|(lower, upper)| ((lower.min, lower.max), (upper.min, upper.max)))
+    }
+
+    #[test]
+    fn root_splits_at_the_initial_pivot() {
+        let price = SearchBand::root(SplitDimension::Price);
+        assert_eq!(bounds(price.split(Operation::Sale)), Some(((None, Some(250_000.0)), (Some(250_000.0), None))));
+        assert_eq!(bounds(price.split(Operation::Rent)), Some(((None, Some(1_200.0)), (Some(1_200.0), None))));
+
+        // Size doesn't depend on whether the home is for sale or rent.
+        let size = SearchBand::root(SplitDimension::Size);
+        for operation in [Operation::Sale, Operation::Rent] {
+            assert_eq!(bounds(size.split(operation)), Some(((None, Some(100.0)), (Some(100.0), None))));
+        }
+    }
+
+    #[test]
+    fn half_open_bands_double_or_halve_their_bound() {
+        let upper = band(SplitDimension::Price, Some(250_000.0), None);
+        assert_eq!(bounds(upper.split(Operation::Sale)), Some(((Some(250_000.0), Some(500_000.0)), (Some(500_000.0), None))));
+
+        let lower = band(SplitDimension::Price, None, Some(250_000.0));
+        assert_eq!(bounds(lower.split(Operation::Sale)), Some(((None, Some(125_000.0)), (Some(125_000.0), Some(250_000.0)))));
+    }
+
+    #[test]
+    fn bounded_bands_split_at_the_rounded_midpoint() {
+        let bounded = band(SplitDimension::Size, Some(60.0), Some(75.0));
+        assert_eq!(bounds(bounded.split(Operation::Rent)), Some(((Some(60.0), Some(68.0)), (Some(68.0), Some(75.0)))));
+    }
+
+    #[test]
+    fn stops_once_the_pivot_no_longer_narrows_the_band() {
+        // The midpoint rounds onto a bound, so neither half would be smaller.
+        assert!(band(SplitDimension::Price, Some(100.0), Some(101.0)).split(Operation::Sale).is_none());
+        assert!(band(SplitDimension::Size, None, Some(1.0)).split(Operation::Sale).is_none());
+        assert!(band(SplitDimension::Size, Some(0.0), None).split(Operation::Sale).is_none());
+        assert!(band(SplitDimension::Price, Some(100.0), Some(102.0)).split(Operation::Sale).is_some());
+    }
+
+    #[test]
+    fn halves_keep_the_dimension_and_get_their_own_keys() {
+        let (lower, upper) = SearchBand::root(SplitDimension::Size).split(Operation::Sale).unwrap();
+        assert_eq!((lower.dimension, upper.dimension), (SplitDimension::Size, SplitDimension::Size));
+        assert_eq!((lower.key().as_str(), upper.key().as_str()), ("size--100", "size-100-"));
+        assert_eq!((lower.query().as_str(), upper.query().as_str()), ("&maxSize=100", "&minSize=100"));
+    }
+}
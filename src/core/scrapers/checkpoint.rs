@@ -0,0 +1,79 @@
+use crate::core::dataset::Coverage;
+use crate::core::storage::Storage;
+use crate::core::types::{Country, Operation, Property};
+
+use std::error::Error;
+
+/// Where a scrape is per (location, property type), persisted after every page so an
+/// interrupted run picks up at the next page instead of spending API quota again.
+/// Scraped properties are held here until the run finishes and they're upserted.
+pub struct ScrapeCheckpoint {
+    storage: Storage,
+    country: Country,
+    operation: Operation,
+}
+
+/// Progress of one (location, property type) pair.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub next_page: u32,
+    /// Nothing left to fetch in this run.
+    pub done: bool,
+    /// Finished with every result the search matched, not cut short by a page cap,
+    /// truncation or a failure.
+    pub complete: bool,
+}
+
+impl ScrapeCheckpoint {
+    pub fn new(storage: Storage, country: Country, operation: Operation) -> Self {
+        Self { storage, country, operation }
+    }
+
+    pub fn open(country: Country, operation: Operation) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(Storage::open_default()?, country, operation))
+    }
+
+    /// Whether an earlier run for this country and operation stopped before finishing.
+    pub fn exists(&self) -> Result<bool, Box<dyn Error>> {
+        self.storage.has_scrape_checkpoint(self.country, self.operation)
+    }
+
+    pub fn progress(&self, location: &str, property_type: &str) -> Result<Progress, Box<dyn Error>> {
+        let progress = self.storage.scrape_progress(self.country, self.operation, location, property_type)?;
+        Ok(progress.unwrap_or(Progress { next_page: 1, done: false, complete: false }))
+    }
+
+    /// Saves a page of `location`, a band of the configured location `search`.
+    pub fn save_page(
+        &self,
+        search: &str,
+        location: &str,
+        property_type: &str,
+        progress: Progress,
+        properties: &[Property],
+    ) -> Result<(), Box<dyn Error>> {
+        self.storage
+            .save_scrape_page(self.country, self.operation, search, location, property_type, progress, properties)
+    }
+
+    pub fn pending_properties(&self) -> Result<Vec<Property>, Box<dyn Error>> {
+        self.storage.scrape_pending(self.country, self.operation)
+    }
+
+    /// The search each pending listing came from, and the searches that finished with
+    /// every result they matched.
+    pub fn coverage(&self) -> Result<Coverage, Box<dyn Error>> {
+        let searches = self
+            .storage
+            .scrape_pending_by_search(self.country, self.operation)?
+            .into_iter()
+            .map(|(search, property)| (property.property_code, search))
+            .collect();
+        let complete = self.storage.complete_searches(self.country, self.operation)?;
+        Ok(Coverage { searches, complete })
+    }
+
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.storage.clear_scrape_checkpoint(self.country, self.operation)
+    }
+}
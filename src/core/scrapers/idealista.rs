@@ -1,170 +1,342 @@
-use crate::core::types::{SuggestionsResponse, ListingsResponse, HomeListing, Location};
+use crate::core::config::ScrapeConfig;
+use crate::core::dataset::Coverage;
+use crate::core::scrapers::Scraper;
+use crate::core::scrapers::band::{SearchBand, SplitDimension};
+use crate::core::scrapers::checkpoint::{Progress, ScrapeCheckpoint};
+use crate::core::scrapers::location_cache::LocationCache;
+use crate::core::scrapers::replay::{self, ResponseMode};
+use crate::core::scrapers::request::{RequestError, RequestLayer, RequestPolicy, ScrapeSummary};
+use crate::core::storage;
+use crate::core::types::{SuggestionsResponse, ListingsResponse, Location, Country, Operation, Property, PropertyType};
 
 use std::error::Error;
 use std::collections::{HashMap, HashSet};
-use reqwest::blocking::Client;
-use std::fs::{File, OpenOptions};
-use std::path::Path;
-use csv::{ReaderBuilder, Writer};
 
 pub struct IdealistaScraper {
-    client: Client,
+    requests: RequestLayer,
     idealista_base_api_url: String,
-    idealista_api_key: String,
-    cached_location_ids: HashSet<String>,
+    headers: Vec<(String, String)>,
+    name: String,
+    country: Country,
+    operation: Operation,
+    config: ScrapeConfig,
+    location_cache: LocationCache,
+    checkpoint: ScrapeCheckpoint,
+    response_mode: ResponseMode,
+    /// "city (property type)" of every search the last run left unfinished.
+    unfinished: Vec<String>,
+}
+
+/// One location/property-type search, shared by every band it's split into.
+struct Search<'a> {
+    location_id: &'a str,
+    city_name: &'a str,
+    property_type: PropertyType,
+    /// Pages this run may still fetch for the search, across all of its bands.
+    pages_left: u32,
 }
 
 impl IdealistaScraper {
-    pub fn new() -> Self {
-        let idealista_base_api_url = std::env::var("IDEALISTA_BASE_API_URL")
-            .expect("Missing IDEALISTA_BASE_API_URL env var");
-        let idealista_api_key = std::env::var("IDEALISTA_API_KEY")
-            .expect("Missing IDEALISTA_API_KEY env var");
-
-        let mut cached_location_ids = HashSet::new();
-        if let Ok(file) = File::open("data/idealista_homes_spain.csv") {
-            let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(file);
-            for result in rdr.records() {
-                if let Ok(record) = result {
-                    if let Some(location_id) = record.get(10) {
-                        cached_location_ids.insert(location_id.to_string());
-                    }
-                }
-            }
+    /// Creates a scraper whose response mode comes from `IDEALISTA_REPLAY_DIR` / `IDEALISTA_RECORD_DIR`.
+    pub fn new(country: Country, operation: Operation, config: ScrapeConfig) -> Result<Self, Box<dyn Error>> {
+        Self::with_response_mode(country, operation, config, ResponseMode::from_env())
+    }
+
+    pub fn with_response_mode(
+        country: Country,
+        operation: Operation,
+        config: ScrapeConfig,
+        response_mode: ResponseMode,
+    ) -> Result<Self, Box<dyn Error>> {
+        // A replay would merge recorded listings into the real dataset and delist whatever
+        // the recording doesn't contain, so it has to run against a scratch database.
+        if response_mode.is_replay() && storage::is_default_database(&storage::default_path()) {
+            return Err(format!(
+                "Replays must not write to {}, set DATABASE_PATH to a scratch database (e.g. /tmp/replay.db)",
+                storage::DATABASE_FILE
+            )
+            .into());
         }
 
-        Self {
-            client: Client::new(),
+        // Replaying never reaches the API, so credentials are only needed otherwise.
+        let (idealista_base_api_url, headers) = if response_mode.is_replay() {
+            (String::new(), Vec::new())
+        } else {
+            (config.api.base_url()?, config.api.request_headers()?)
+        };
+
+        let source = if response_mode.is_replay() { "Idealista replay" } else { "Idealista" };
+        let name = format!("{} {} ({})", source, country.label(), operation.as_query());
+
+        Ok(Self {
+            requests: RequestLayer::new(RequestPolicy::from_env()),
             idealista_base_api_url,
-            idealista_api_key,
-            cached_location_ids,
-        }
+            headers,
+            name,
+            country,
+            operation,
+            config,
+            location_cache: LocationCache::from_env()?,
+            checkpoint: ScrapeCheckpoint::open(country, operation)?,
+            response_mode,
+            unfinished: Vec::new(),
+        })
     }
 
     pub fn fetch_location_ids(&mut self, cities: &[&str]) -> HashMap<String, String> {
         let mut location_map = HashMap::new();
 
         for city_name in cities {
-            if self.cached_location_ids.contains(&city_name.to_string()) {
-                println!("Skipping city {}: Already fetched", city_name);
+            if self.requests.quota_exhausted() {
+                break;
+            }
+
+            // Cached ids would make a replay depend on local state, so replays always use the recording.
+            if !self.response_mode.is_replay()
+                && let Some(location_id) = self.location_cache.get(self.country, city_name)
+            {
+                println!("Using cached location id for {}", city_name);
+                location_map.insert(city_name.to_string(), location_id);
                 continue;
             }
 
             let url = format!(
-                "{}/getsuggestions?prefix={}&location=es&propertyType=homes&operation=sale",
-                &self.idealista_base_api_url, city_name
+                "{}/getsuggestions?prefix={}&location={}&propertyType=homes&operation={}",
+                &self.idealista_base_api_url, city_name, self.country.as_query(), self.operation.as_query()
             );
 
-            let response = self.client
-                .get(&url)
-                .header("x-rapidapi-host", "idealista7.p.rapidapi.com")
-                .header("x-rapidapi-key", &self.idealista_api_key)
-                .send();
-
-            if let Ok(res) = response {
-                let response_text = res.text().unwrap_or_else(|_| "Failed to read response body".to_string());
-                if let Ok(data) = serde_json::from_str::<SuggestionsResponse>(&response_text) {
-                    if let Some(best_match) = data.locations.iter().max_by_key(|loc| loc.total) {
-                        if let Some(location_id) = &best_match.locationId {
-                            location_map.insert(city_name.to_string(), location_id.clone());
-                            self.cached_location_ids.insert(city_name.to_string());
-                        }
-                    }
+            let context = format!("getsuggestions {}", city_name);
+            let key = replay::suggestions_key(self.country.as_query(), self.operation.as_query(), city_name);
+            let Ok(response_text) = self.get(&url, &key, &context) else { continue };
+
+            let data = match serde_json::from_str::<SuggestionsResponse>(&response_text) {
+                Ok(data) => data,
+                Err(e) => {
+                    self.requests.record_failure(&context, RequestError::Decode(e.to_string()));
+                    continue;
                 }
+            };
+
+            let best_match = data.locations.iter().max_by_key(|loc| loc.total);
+            let Some(location_id) = best_match.and_then(|loc| loc.locationId.clone()) else {
+                self.requests.record_failure(&context, RequestError::Decode("no matching location".to_string()));
+                continue;
+            };
+
+            location_map.insert(city_name.to_string(), location_id.clone());
+            if !self.response_mode.is_replay()
+                && let Err(e) = self.location_cache.insert(self.country, city_name, &location_id)
+            {
+                println!("Failed to cache location id for {}: {}", city_name, e);
             }
         }
 
         location_map
     }
 
-    pub fn scrape_listings(&self, location_id: &str, city_name: &str) -> Vec<HomeListing> {
-        let url = format!(
-            "{}/listhomes?order=relevance&operation=sale&propertyType=homes&locationId={}&locationName={}&numPage=1&maxItems=40&location=es&locale=es",
-            self.idealista_base_api_url, location_id, city_name
-        );
-
-        let response = self.client
-            .get(&url)
-            .header("x-rapidapi-host", "idealista7.p.rapidapi.com")
-            .header("x-rapidapi-key", &self.idealista_api_key)
-            .send();
-
-        if let Ok(res) = response {
-            let response_text = res.text().unwrap_or_else(|_| "Failed to read response body".to_string());
-            if let Ok(data) = serde_json::from_str::<ListingsResponse>(&response_text) {
-                return data.elementList;
+    /// Walks the result pages of one location, continuing from the checkpoint if an earlier
+    /// run was interrupted. Searches with more pages than the API serves are split into price
+    /// (or size) bands, and the bands' results are merged and deduplicated by property code.
+    /// At most `max_pages` pages are fetched in total, however many bands there are.
+    pub fn scrape_listings(
+        &mut self,
+        location_id: &str,
+        city_name: &str,
+        property_type: PropertyType,
+        max_pages: u32,
+        split_by: SplitDimension,
+    ) -> Result<Vec<Property>, Box<dyn Error>> {
+        let mut search = Search { location_id, city_name, property_type, pages_left: max_pages };
+        let mut properties = Vec::new();
+        let progress = self.scrape_band(&mut search, SearchBand::root(split_by), 0, &mut properties)?;
+        if !progress.done {
+            self.unfinished.push(format!("{} ({})", city_name, property_type.as_query()));
+        }
+        if !progress.complete {
+            println!("{} ({}) was not scraped completely, its missing listings won't be delisted", city_name, property_type.as_query());
+        }
+
+        let mut seen = HashSet::new();
+        properties.retain(|property| seen.insert(property.property_code.clone()));
+        Ok(properties)
+    }
+
+    /// Scrapes a band of `search` and returns its final progress: done once nothing is left
+    /// to fetch, complete only if every page was fetched.
+    fn scrape_band(&mut self, search: &mut Search, band: SearchBand, depth: u32, properties: &mut Vec<Property>) -> Result<Progress, Box<dyn Error>> {
+        let Search { location_id, city_name, property_type, .. } = *search;
+        let api_page_limit = self.config.api_page_limit;
+        let label = if band.is_root() {
+            city_name.to_string()
+        } else {
+            format!("{} [{}]", city_name, band.key())
+        };
+
+        let type_query = property_type.as_query();
+
+        let progress = self.checkpoint.progress(&label, type_query)?;
+        if progress.done {
+            println!("Skipping {} ({}): finished in an earlier run", label, type_query);
+            return Ok(progress);
+        }
+        if progress.next_page > 1 {
+            println!("Resuming {} ({}) at page {}", label, type_query, progress.next_page);
+        }
+
+        let mut num_page = progress.next_page;
+        let mut progress = Progress { done: false, complete: false, ..progress };
+
+        loop {
+            // Out of budget: the band is done for this run but stays incomplete.
+            if search.pages_left == 0 {
+                println!("Page budget used up before page {} of {} ({})", num_page, label, type_query);
+                progress = Progress { next_page: num_page, done: true, complete: false };
+                self.checkpoint.save_page(city_name, &label, type_query, progress, &[])?;
+                break;
+            }
+            search.pages_left -= 1;
+
+            let url = format!(
+                "{}/listhomes?order=relevance&operation={}&propertyType={}&locationId={}&locationName={}&numPage={}&maxItems=40&location={}&locale={}{}",
+                self.idealista_base_api_url, self.operation.as_query(), type_query, location_id, city_name, num_page,
+                self.country.as_query(), self.country.locale(), band.query()
+            );
+
+            let context = format!("listhomes {} ({}) page {}", label, type_query, num_page);
+            let key = replay::listings_key(self.operation.as_query(), type_query, location_id, &band.key(), num_page);
+            let Ok(response_text) = self.get(&url, &key, &context) else { break };
+
+            let data = match serde_json::from_str::<ListingsResponse>(&response_text) {
+                Ok(data) => data,
+                Err(e) => {
+                    self.requests.record_failure(&context, RequestError::Decode(e.to_string()));
+                    break;
+                }
+            };
+
+            let total_pages = data.totalPages;
+
+            // A split band is only saved once both halves are done: until then a resumed run
+            // refetches this page, splits at the same pivot and picks up the halves from their
+            // own checkpoints.
+            if total_pages > api_page_limit
+                && depth < self.config.max_split_depth
+                && let Some((lower, upper)) = band.split(self.operation)
+            {
+                println!("{} has {} pages, splitting into {} and {}", label, total_pages, lower.key(), upper.key());
+                let lower = self.scrape_band(search, lower, depth + 1, properties)?;
+                let upper = self.scrape_band(search, upper, depth + 1, properties)?;
+                let progress = Progress { next_page: num_page, done: lower.done && upper.done, complete: lower.complete && upper.complete };
+                if progress.done {
+                    self.checkpoint.save_page(city_name, &label, type_query, progress, &[])?;
+                }
+                return Ok(progress);
+            }
+
+            let page_is_empty = data.elementList.is_empty();
+            let page: Vec<Property> = data.elementList
+                .into_iter()
+                .map(|home| Property { property_type, ..Property::from(home) })
+                .collect();
+
+            println!("Fetched page {}/{} for {}", num_page, total_pages.min(api_page_limit), label);
+
+            // Pages past the API's limit come back empty, so a band that couldn't be split
+            // below the limit ends there without having returned everything.
+            let last_page = page_is_empty || num_page >= total_pages.min(api_page_limit);
+            let complete = last_page && total_pages <= api_page_limit;
+            let done = last_page || search.pages_left == 0;
+            progress = Progress { next_page: num_page + 1, done, complete };
+            self.checkpoint.save_page(city_name, &label, type_query, progress, &page)?;
+            properties.extend(page);
+
+            if done {
+                break;
             }
+            num_page += 1;
         }
 
-        vec![]
+        Ok(progress)
     }
 
-    pub fn scrape_all_homes_spain(&mut self) -> Result<(), Box<dyn Error>> {
-        let cities = [
-            "Madrid", "Barcelona", "Seville", "Valencia", "Málaga", "Zaragoza", "A Coruña",
-            "Gijón", "San Sebastián", "Pamplona", "Santander", "Burgos", "León", "Valladolid",
-            "Salamanca", "Bilbao", "Vitoria-Gasteiz", "Alicante", "Castellón de la Plana", "Tarragona",
-        ];
+    /// Fetches `url`, or replays/records the response stored under `key`.
+    fn get(&mut self, url: &str, key: &str, context: &str) -> Result<String, RequestError> {
+        if self.response_mode.is_replay() {
+            return self.response_mode.replay(key).ok_or_else(|| {
+                let error = RequestError::NotRecorded(key.to_string());
+                self.requests.record_failure(context, error.clone());
+                error
+            });
+        }
 
-        let location_ids = self.fetch_location_ids(&cities);
-        let locations: Vec<Location> = cities
+        let headers: Vec<(&str, &str)> = self.headers
             .iter()
-            .filter_map(|&city| {
-                location_ids.get(city).map(|id| {
-                    Location {
-                        locationId: id.clone(),
-                        name: city.to_string(),
-                    }
-                })
-            })
+            .map(|(name, value)| (name.as_str(), value.as_str()))
             .collect();
+        let body = self.requests.get(url, &headers, context)?;
 
-        let csv_file_path = "data/idealista_homes_spain.csv";
-        let file_exists = Path::new(csv_file_path).exists();
+        if let Err(e) = self.response_mode.record(key, &body) {
+            println!("Failed to record response {}: {}", key, e);
+        }
+        Ok(body)
+    }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .create(true)
-            .open(csv_file_path)?;
+    /// Scrapes every location configured for the scraper's country and operation.
+    pub fn scrape_all_locations(&mut self) -> Result<Vec<Property>, Box<dyn Error>> {
+        let config = self.config.clone();
+        let location_configs: Vec<_> = config.locations_for(self.country, self.operation).collect();
+        let cities: Vec<&str> = location_configs.iter().map(|location| location.name.as_str()).collect();
 
-        let mut writer = Writer::from_writer(file);
+        let location_ids = self.fetch_location_ids(&cities);
+        self.unfinished.clear();
 
-        if !file_exists {
-            writer.write_record(&[
-                "Property Code", "Price (€)", "Size (m²)", "Floor", "Address", "Province",
-                "Municipality", "District", "Neighborhood", "Latitude", "Longitude", "Has Lift",
-                "Price by Area", "Rooms", "Bathrooms", "Swimming Pool", "Garden", "Garage", "URL",
-            ])?;
-        }
+        for location_config in location_configs {
+            let skipped = || location_config.property_types.iter().map(|t| format!("{} ({})", location_config.name, t.as_query()));
+            if self.requests.quota_exhausted() {
+                println!("API quota exhausted, stopping before {}", location_config.name);
+                self.unfinished.extend(skipped());
+                continue;
+            }
+
+            let Some(id) = location_ids.get(&location_config.name) else {
+                self.unfinished.extend(skipped());
+                continue;
+            };
+            let location = Location {
+                locationId: id.clone(),
+                name: location_config.name.clone(),
+            };
+            let max_pages = config.max_pages_for(location_config);
+            let split_by = config.split_by_for(location_config);
 
-        for location in locations {
-            let listings = self.scrape_listings(&location.locationId, &location.name);
-            for home in &listings {
-                writer.write_record(&[
-                    home.propertyCode.clone(),
-                    home.price.to_string(),
-                    home.size.map_or("N/A".to_string(), |s| s.to_string()),
-                    home.floor.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.address.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.province.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.municipality.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.district.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.neighborhood.clone().unwrap_or_else(|| "N/A".to_string()),
-                    home.latitude.map_or("N/A".to_string(), |lat| lat.to_string()),
-                    home.longitude.map_or("N/A".to_string(), |lon| lon.to_string()),
-                    home.hasLift.map_or("N/A".to_string(), |lift| lift.to_string()),
-                    home.priceByArea.map_or("N/A".to_string(), |pba| pba.to_string()),
-                    home.rooms.map_or("N/A".to_string(), |r| r.to_string()),
-                    home.bathrooms.map_or("N/A".to_string(), |b| b.to_string()),
-                    home.swimmingPool.map_or("N/A".to_string(), |sp| sp.to_string()),
-                    home.garden.map_or("N/A".to_string(), |g| g.to_string()),
-                    home.garage.map_or("N/A".to_string(), |gr| gr.to_string()),
-                    home.url.clone().unwrap_or_else(|| "N/A".to_string()),
-                ])?;
+            for &property_type in &location_config.property_types {
+                self.scrape_listings(&location.locationId, &location.name, property_type, max_pages, split_by)?;
             }
         }
 
-        Ok(())
+        // Includes listings checkpointed by an interrupted run, not just this one.
+        self.checkpoint.pending_properties()
+    }
+}
+
+impl Scraper for IdealistaScraper {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn scrape(&mut self) -> Result<Vec<Property>, Box<dyn Error>> {
+        self.scrape_all_locations()
+    }
+
+    fn summary(&self) -> Option<&ScrapeSummary> {
+        Some(self.requests.summary())
+    }
+
+    fn coverage(&self) -> Result<Coverage, Box<dyn Error>> {
+        self.checkpoint.coverage()
+    }
+
+    fn unfinished_searches(&self) -> &[String] {
+        &self.unfinished
     }
 }
@@ -0,0 +1,58 @@
+use crate::core::storage::Storage;
+use crate::core::types::Country;
+
+use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_TTL_DAYS: u64 = 30;
+
+/// (country, city name) -> Idealista locationId, persisted in the `locations` table so repeated
+/// runs don't spend API quota on `getsuggestions`. Entries older than the TTL are ignored.
+pub struct LocationCache {
+    storage: Storage,
+    ttl: Duration,
+}
+
+impl LocationCache {
+    pub fn new(storage: Storage, ttl: Duration) -> Self {
+        Self { storage, ttl }
+    }
+
+    /// Opens the default database with the TTL from `LOCATION_CACHE_TTL_DAYS`.
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let ttl_days = std::env::var("LOCATION_CACHE_TTL_DAYS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_DAYS);
+
+        Ok(Self::new(Storage::open_default()?, Duration::from_secs(ttl_days * 24 * 60 * 60)))
+    }
+
+    /// Returns the cached location id for `city_name` in `country` if it hasn't expired.
+    pub fn get(&self, country: Country, city_name: &str) -> Option<String> {
+        let (location_id, fetched_at) = self.storage.location(country, city_name).ok()??;
+        let age = now().saturating_sub(fetched_at);
+
+        if age <= self.ttl.as_secs() {
+            Some(location_id)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&self, country: Country, city_name: &str, location_id: &str) -> Result<(), Box<dyn Error>> {
+        self.storage.save_location(country, city_name, location_id, now())
+    }
+
+    /// Drops one city of `country`, or every city of it when `city_name` is `None`. Returns how many entries were removed.
+    pub fn invalidate(&self, country: Country, city_name: Option<&str>) -> Result<usize, Box<dyn Error>> {
+        self.storage.delete_locations(country, city_name)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
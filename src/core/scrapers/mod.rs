@@ -1 +1,42 @@
+pub mod band;
+pub mod checkpoint;
+pub mod feed;
 pub mod idealista;
+pub mod location_cache;
+pub mod replay;
+pub mod request;
+
+use crate::core::dataset::Coverage;
+use crate::core::scrapers::request::ScrapeSummary;
+use crate::core::types::Property;
+
+use std::error::Error;
+
+/// A listing-portal backend. Each implementation lives in its own module
+/// beside `idealista.rs` and normalizes whatever the portal returns into
+/// `Property` records, so the dataset and the model never see portal types.
+pub trait Scraper {
+    /// Name shown in the CLI when picking a data source.
+    fn name(&self) -> &str;
+
+    /// Fetches every listing the backend is configured for.
+    fn scrape(&mut self) -> Result<Vec<Property>, Box<dyn Error>>;
+
+    /// Requests made and failures hit by the last `scrape`, for backends that talk to an API.
+    fn summary(&self) -> Option<&ScrapeSummary> {
+        None
+    }
+
+    /// Which search returned each listing of the last `scrape`, and the searches it got
+    /// every listing of. Stored listings those searches last returned that weren't returned
+    /// again are marked delisted; by default nothing is.
+    fn coverage(&self) -> Result<Coverage, Box<dyn Error>> {
+        Ok(Coverage::default())
+    }
+
+    /// Searches the last `scrape` didn't get to the end of, e.g. because of a failure or the
+    /// API quota. A resumable backend keeps its checkpoint until this is empty.
+    fn unfinished_searches(&self) -> &[String] {
+        &[]
+    }
+}
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where `IdealistaScraper` gets its responses from. Recorded responses are raw JSON
+/// bodies named after the request (`listhomes-sale-homes-<locationId>-p1.json`), so a
+/// recording directory can be replayed later without an API key or network.
+#[derive(Debug, Clone)]
+pub enum ResponseMode {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl ResponseMode {
+    /// `IDEALISTA_REPLAY_DIR` takes precedence over `IDEALISTA_RECORD_DIR`; neither means live.
+    pub fn from_env() -> Self {
+        if let Ok(dir) = std::env::var("IDEALISTA_REPLAY_DIR") {
+            ResponseMode::Replay(PathBuf::from(dir))
+        } else if let Ok(dir) = std::env::var("IDEALISTA_RECORD_DIR") {
+            ResponseMode::Record(PathBuf::from(dir))
+        } else {
+            ResponseMode::Live
+        }
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, ResponseMode::Replay(_))
+    }
+
+    /// The recorded body for `key`, if replaying and it exists.
+    pub fn replay(&self, key: &str) -> Option<String> {
+        match self {
+            ResponseMode::Replay(dir) => fs::read_to_string(response_path(dir, key)).ok(),
+            _ => None,
+        }
+    }
+
+    /// Saves a live response body under `key` when recording.
+    pub fn record(&self, key: &str, body: &str) -> Result<(), Box<dyn Error>> {
+        if let ResponseMode::Record(dir) = self {
+            fs::create_dir_all(dir)?;
+            fs::write(response_path(dir, key), body)?;
+        }
+        Ok(())
+    }
+}
+
+/// Key of a recorded `getsuggestions` response. City names repeat across countries, so the country is part of it.
+pub fn suggestions_key(country: &str, operation: &str, city_name: &str) -> String {
+    format!("getsuggestions-{}-{}-{}", country, operation, city_name)
+}
+
+/// Key of a recorded `listhomes` page. `band` is empty for an unsplit search. Location ids
+/// already name their country (`0-EU-ES-...`), so the country isn't repeated here.
+pub fn listings_key(operation: &str, property_type: &str, location_id: &str, band: &str, num_page: u32) -> String {
+    if band.is_empty() {
+        format!("listhomes-{}-{}-{}-p{}", operation, property_type, location_id, num_page)
+    } else {
+        format!("listhomes-{}-{}-{}-{}-p{}", operation, property_type, location_id, band, num_page)
+    }
+}
+
+/// File a response recorded under `key` lives in.
+pub fn response_path(dir: &Path, key: &str) -> PathBuf {
+    let file_name: String = key
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    dir.join(format!("{}.json", file_name))
+}
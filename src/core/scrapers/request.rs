@@ -0,0 +1,258 @@
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How politely a scraper talks to its API. Read from env vars so it can be tuned
+/// to the RapidAPI plan without recompiling.
+#[derive(Debug, Clone)]
+pub struct RequestPolicy {
+    pub requests_per_second: f64,
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Stop sending requests once the plan has this many requests left.
+    pub quota_reserve: u32,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 2.0,
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            quota_reserve: 0,
+        }
+    }
+}
+
+impl RequestPolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |key: &str| std::env::var(key).ok();
+
+        Self {
+            requests_per_second: var("REQUESTS_PER_SECOND")
+                .and_then(|v| v.parse().ok())
+                .filter(|rps: &f64| *rps > 0.0)
+                .unwrap_or(defaults.requests_per_second),
+            max_retries: var("MAX_RETRIES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_retries),
+            initial_backoff: var("INITIAL_BACKOFF_MS")
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.initial_backoff),
+            max_backoff: var("MAX_BACKOFF_MS")
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_backoff),
+            quota_reserve: var("RAPIDAPI_QUOTA_RESERVE")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.quota_reserve),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RequestError {
+    Network(String),
+    Status(u16),
+    QuotaExhausted,
+    Decode(String),
+    /// Replay mode had no recorded response for this request.
+    NotRecorded(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Network(e) => write!(f, "network error: {}", e),
+            RequestError::Status(status) => write!(f, "HTTP {}", status),
+            RequestError::QuotaExhausted => write!(f, "API quota exhausted"),
+            RequestError::Decode(e) => write!(f, "unexpected response: {}", e),
+            RequestError::NotRecorded(key) => write!(f, "no recorded response for {}", key),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestFailure {
+    /// What was being fetched, e.g. "listhomes Madrid page 3".
+    pub context: String,
+    pub error: RequestError,
+}
+
+/// What happened during a scrape, printed once it finishes.
+#[derive(Debug, Clone, Default)]
+pub struct ScrapeSummary {
+    pub requests: usize,
+    pub retries: usize,
+    pub failures: Vec<RequestFailure>,
+    /// Requests left on the RapidAPI plan, from the last `x-ratelimit-requests-remaining` header.
+    pub quota_remaining: Option<u32>,
+    pub quota_limit: Option<u32>,
+}
+
+impl fmt::Display for ScrapeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Requests sent: {} ({} retries)", self.requests, self.retries)?;
+        match (self.quota_remaining, self.quota_limit) {
+            (Some(remaining), Some(limit)) => writeln!(f, "API quota remaining: {}/{}", remaining, limit)?,
+            (Some(remaining), None) => writeln!(f, "API quota remaining: {}", remaining)?,
+            _ => {}
+        }
+
+        if self.failures.is_empty() {
+            write!(f, "No failed requests.")
+        } else {
+            write!(f, "Failed requests: {}", self.failures.len())?;
+            for failure in &self.failures {
+                write!(f, "\n  - {}: {}", failure.context, failure.error)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Blocking HTTP GETs with throttling, exponential backoff on 429/5xx and
+/// RapidAPI quota tracking. Every failure is kept for the `ScrapeSummary`.
+pub struct RequestLayer {
+    client: Client,
+    policy: RequestPolicy,
+    last_request: Option<Instant>,
+    summary: ScrapeSummary,
+}
+
+impl RequestLayer {
+    pub fn new(policy: RequestPolicy) -> Self {
+        Self {
+            client: Client::new(),
+            policy,
+            last_request: None,
+            summary: ScrapeSummary::default(),
+        }
+    }
+
+    /// Fetches `url` and returns the response body. Failures are recorded under `context`.
+    pub fn get(&mut self, url: &str, headers: &[(&str, &str)], context: &str) -> Result<String, RequestError> {
+        let result = self.get_with_retries(url, headers);
+        if let Err(e) = &result {
+            self.record_failure(context, e.clone());
+        }
+        result
+    }
+
+    /// Records a failure found after the request itself succeeded, e.g. a body that didn't parse.
+    pub fn record_failure(&mut self, context: &str, error: RequestError) {
+        println!("Request failed ({}): {}", context, error);
+        self.summary.failures.push(RequestFailure {
+            context: context.to_string(),
+            error,
+        });
+    }
+
+    pub fn summary(&self) -> &ScrapeSummary {
+        &self.summary
+    }
+
+    /// Whether the plan is down to the configured reserve, after which no more requests are sent.
+    pub fn quota_exhausted(&self) -> bool {
+        self.summary
+            .quota_remaining
+            .is_some_and(|remaining| remaining <= self.policy.quota_reserve)
+    }
+
+    fn get_with_retries(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<String, RequestError> {
+        let mut attempt = 0;
+
+        loop {
+            if self.quota_exhausted() {
+                return Err(RequestError::QuotaExhausted);
+            }
+
+            self.throttle();
+            self.summary.requests += 1;
+
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+
+            let (error, retry_after) = match request.send() {
+                Ok(res) => {
+                    self.track_quota(&res);
+                    let status = res.status();
+
+                    if status.is_success() {
+                        return res.text().map_err(|e| RequestError::Decode(e.to_string()));
+                    }
+                    if !is_retryable(status) {
+                        return Err(RequestError::Status(status.as_u16()));
+                    }
+                    (RequestError::Status(status.as_u16()), retry_after(&res))
+                }
+                Err(e) => (RequestError::Network(e.to_string()), None),
+            };
+
+            if attempt >= self.policy.max_retries {
+                return Err(error);
+            }
+
+            // A server asking for a longer wait than `max_backoff` doesn't get to stall the scrape.
+            let backoff = retry_after.map_or_else(|| self.backoff(attempt), |wait| wait.min(self.policy.max_backoff));
+            println!("Request failed with {}, retrying in {:.1}s", error, backoff.as_secs_f64());
+            thread::sleep(backoff);
+
+            attempt += 1;
+            self.summary.retries += 1;
+        }
+    }
+
+    fn throttle(&mut self) {
+        let interval = Duration::from_secs_f64(1.0 / self.policy.requests_per_second);
+        if let Some(last_request) = self.last_request {
+            let elapsed = last_request.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_request = Some(Instant::now());
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self.policy.initial_backoff.saturating_mul(2u32.saturating_pow(attempt));
+        backoff.min(self.policy.max_backoff)
+    }
+
+    fn track_quota(&mut self, res: &Response) {
+        let header = |name: &str| {
+            res.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u32>().ok())
+        };
+
+        if let Some(remaining) = header("x-ratelimit-requests-remaining") {
+            self.summary.quota_remaining = Some(remaining);
+        }
+        if let Some(limit) = header("x-ratelimit-requests-limit") {
+            self.summary.quota_limit = Some(limit);
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
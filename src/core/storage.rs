@@ -0,0 +1,978 @@
+use crate::core::cervo::Hyperparameters;
+use crate::core::dataset::{self, Coverage, Scope, StoredListing, UpsertSummary};
+use crate::core::metrics::Metric;
+use crate::core::preprocessing::Target;
+use crate::core::scrapers::checkpoint::Progress;
+use crate::core::history::{self, EventKind, ListingEvent};
+use crate::core::types::{Country, Floor, Operation, Property, PropertyType};
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, Row};
+use serde::Deserialize;
+
+pub const DATABASE_FILE: &str = "data/housevaluation.db";
+/// The location cache used before the database, a JSON map of Spanish city name to location.
+const LEGACY_LOCATION_CACHE_FILE: &str = "data/idealista_location_cache.json";
+
+/// Schema migrations, applied in order. `PRAGMA user_version` holds how many have run,
+/// so a new schema change is a new entry at the end, never an edit to an old one.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE listings (
+        operation TEXT NOT NULL,
+        property_code TEXT NOT NULL,
+        price_eur REAL NOT NULL,
+        size_sqm REAL,
+        floor TEXT,
+        address TEXT NOT NULL,
+        province TEXT NOT NULL,
+        municipality TEXT NOT NULL,
+        district TEXT NOT NULL,
+        neighborhood TEXT NOT NULL,
+        latitude REAL,
+        longitude REAL,
+        has_lift INTEGER,
+        price_per_sqm REAL,
+        rooms INTEGER,
+        bathrooms INTEGER,
+        swimming_pool INTEGER,
+        garden INTEGER,
+        garage INTEGER,
+        url TEXT NOT NULL,
+        first_seen TEXT,
+        last_seen TEXT,
+        delisted_at TEXT,
+        PRIMARY KEY (operation, property_code)
+    );
+    CREATE INDEX listings_municipality ON listings (operation, municipality);
+
+    CREATE TABLE price_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        operation TEXT NOT NULL,
+        property_code TEXT NOT NULL,
+        date TEXT NOT NULL,
+        event TEXT NOT NULL,
+        old_price REAL,
+        new_price REAL
+    );
+    CREATE INDEX price_history_listing ON price_history (operation, property_code);
+
+    CREATE TABLE locations (
+        name TEXT PRIMARY KEY,
+        location_id TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE model_runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        operation TEXT NOT NULL,
+        trained_at TEXT NOT NULL,
+        model_path TEXT NOT NULL,
+        samples INTEGER NOT NULL,
+        penalty REAL NOT NULL,
+        l1_ratio REAL NOT NULL,
+        score REAL NOT NULL
+    );",
+    "CREATE TABLE scrape_progress (
+        operation TEXT NOT NULL,
+        location TEXT NOT NULL,
+        property_type TEXT NOT NULL,
+        next_page INTEGER NOT NULL,
+        done INTEGER NOT NULL,
+        PRIMARY KEY (operation, location, property_type)
+    );
+
+    CREATE TABLE scrape_pending (
+        operation TEXT NOT NULL,
+        property_code TEXT NOT NULL,
+        property TEXT NOT NULL,
+        PRIMARY KEY (operation, property_code)
+    );",
+    "ALTER TABLE listings ADD COLUMN property_type TEXT NOT NULL DEFAULT 'homes';
+    CREATE INDEX listings_property_type ON listings (operation, property_type);
+    ALTER TABLE model_runs ADD COLUMN property_type TEXT NOT NULL DEFAULT 'homes';",
+    // Country joins the keys of every per-market table; existing rows were all scraped in Spain.
+    "CREATE TABLE listings_by_country (
+        country TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        property_code TEXT NOT NULL,
+        price_eur REAL NOT NULL,
+        size_sqm REAL,
+        floor TEXT,
+        address TEXT NOT NULL,
+        province TEXT NOT NULL,
+        municipality TEXT NOT NULL,
+        district TEXT NOT NULL,
+        neighborhood TEXT NOT NULL,
+        latitude REAL,
+        longitude REAL,
+        has_lift INTEGER,
+        price_per_sqm REAL,
+        rooms INTEGER,
+        bathrooms INTEGER,
+        swimming_pool INTEGER,
+        garden INTEGER,
+        garage INTEGER,
+        url TEXT NOT NULL,
+        first_seen TEXT,
+        last_seen TEXT,
+        delisted_at TEXT,
+        property_type TEXT NOT NULL,
+        PRIMARY KEY (country, operation, property_code)
+    );
+    INSERT INTO listings_by_country SELECT 'es', operation, property_code, price_eur, size_sqm, floor,
+        address, province, municipality, district, neighborhood, latitude, longitude, has_lift,
+        price_per_sqm, rooms, bathrooms, swimming_pool, garden, garage, url, first_seen, last_seen,
+        delisted_at, property_type FROM listings;
+    DROP TABLE listings;
+    ALTER TABLE listings_by_country RENAME TO listings;
+    CREATE INDEX listings_municipality ON listings (country, operation, municipality);
+    CREATE INDEX listings_property_type ON listings (country, operation, property_type);
+
+    ALTER TABLE price_history ADD COLUMN country TEXT NOT NULL DEFAULT 'es';
+    DROP INDEX price_history_listing;
+    CREATE INDEX price_history_listing ON price_history (country, operation, property_code);
+
+    ALTER TABLE model_runs ADD COLUMN country TEXT NOT NULL DEFAULT 'es';
+
+    CREATE TABLE locations_by_country (
+        country TEXT NOT NULL,
+        name TEXT NOT NULL,
+        location_id TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        PRIMARY KEY (country, name)
+    );
+    INSERT INTO locations_by_country SELECT 'es', name, location_id, fetched_at FROM locations;
+    DROP TABLE locations;
+    ALTER TABLE locations_by_country RENAME TO locations;
+
+    CREATE TABLE scrape_progress_by_country (
+        country TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        location TEXT NOT NULL,
+        property_type TEXT NOT NULL,
+        next_page INTEGER NOT NULL,
+        done INTEGER NOT NULL,
+        PRIMARY KEY (country, operation, location, property_type)
+    );
+    INSERT INTO scrape_progress_by_country SELECT 'es', operation, location, property_type, next_page, done FROM scrape_progress;
+    DROP TABLE scrape_progress;
+    ALTER TABLE scrape_progress_by_country RENAME TO scrape_progress;
+
+    CREATE TABLE scrape_pending_by_country (
+        country TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        property_code TEXT NOT NULL,
+        property TEXT NOT NULL,
+        PRIMARY KEY (country, operation, property_code)
+    );
+    INSERT INTO scrape_pending_by_country SELECT 'es', operation, property_code, property FROM scrape_pending;
+    DROP TABLE scrape_pending;
+    ALTER TABLE scrape_pending_by_country RENAME TO scrape_pending;",
+    "ALTER TABLE listings ADD COLUMN status TEXT;
+    ALTER TABLE listings ADD COLUMN exterior INTEGER;
+    ALTER TABLE listings ADD COLUMN energy_rating TEXT;
+    ALTER TABLE listings ADD COLUMN num_photos INTEGER;
+    ALTER TABLE listings ADD COLUMN description TEXT;
+    ALTER TABLE listings ADD COLUMN has_parking_space INTEGER;
+    ALTER TABLE listings ADD COLUMN parking_included INTEGER;
+    ALTER TABLE listings ADD COLUMN new_development INTEGER;",
+    "ALTER TABLE listings ADD COLUMN sub_typology TEXT;",
+    // Runs before this one all scored R², even though selection kept the lowest score.
+    "ALTER TABLE model_runs ADD COLUMN metric TEXT NOT NULL DEFAULT 'r2';",
+    "ALTER TABLE model_runs ADD COLUMN target TEXT NOT NULL DEFAULT 'price';",
+    // Gradient-boosted models have no penalty or L1 ratio; every run keeps its hyperparameters as JSON.
+    "CREATE TABLE model_runs_by_kind (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        country TEXT NOT NULL,
+        operation TEXT NOT NULL,
+        property_type TEXT NOT NULL,
+        trained_at TEXT NOT NULL,
+        model_path TEXT NOT NULL,
+        samples INTEGER NOT NULL,
+        model_kind TEXT NOT NULL,
+        target TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        penalty REAL,
+        l1_ratio REAL,
+        hyperparameters TEXT,
+        score REAL NOT NULL
+    );
+    INSERT INTO model_runs_by_kind SELECT id, country, operation, property_type, trained_at, model_path,
+        samples, 'elastic_net', target, metric, penalty, l1_ratio, NULL, score FROM model_runs;
+    DROP TABLE model_runs;
+    ALTER TABLE model_runs_by_kind RENAME TO model_runs;",
+    // Whether a finished search returned everything, and which search each pending listing came from,
+    // so only completely scraped municipalities get delisted.
+    "ALTER TABLE scrape_progress ADD COLUMN complete INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE scrape_pending ADD COLUMN search TEXT NOT NULL DEFAULT '';",
+    // Delisting is scoped to the search that last returned a listing, not to its municipality,
+    // so a search that covers part of a municipality can't delist the rest of it.
+    "ALTER TABLE listings ADD COLUMN search TEXT;
+    ALTER TABLE scrape_progress ADD COLUMN search TEXT NOT NULL DEFAULT '';",
+];
+
+const LISTING_COLUMNS: &str = "property_code, price_eur, size_sqm, floor, address, province, municipality,
+    district, neighborhood, latitude, longitude, has_lift, price_per_sqm, rooms, bathrooms,
+    swimming_pool, garden, garage, url, first_seen, last_seen, delisted_at, property_type, status,
+    exterior, energy_rating, num_photos, description, has_parking_space, parking_included, new_development,
+    sub_typology";
+
+/// A finished training run, kept so model quality can be compared over time.
+#[derive(Debug, Clone)]
+pub struct ModelRun {
+    pub country: Country,
+    pub operation: Operation,
+    pub property_type: PropertyType,
+    pub model_path: String,
+    pub samples: usize,
+    pub target: Target,
+    /// Cross-validation metric `score` is measured in.
+    pub metric: Metric,
+    pub hyperparameters: Hyperparameters,
+    pub score: f64,
+}
+
+/// The SQLite database holding listings, their price history, cached Idealista
+/// location ids and model runs. It is the source of truth; the dataset CSVs are
+/// only an import/export format.
+pub struct Storage {
+    conn: Connection,
+}
+
+/// `DATABASE_PATH`, or `data/housevaluation.db` when it isn't set.
+pub fn default_path() -> String {
+    std::env::var("DATABASE_PATH").unwrap_or_else(|_| DATABASE_FILE.to_string())
+}
+
+/// Whether `path` is `data/housevaluation.db`, however it's spelled.
+pub fn is_default_database(path: &str) -> bool {
+    let default = Path::new(DATABASE_FILE);
+    match (fs::canonicalize(path), fs::canonicalize(default)) {
+        (Ok(path), Ok(default)) => path == default,
+        _ => Path::new(path) == default,
+    }
+}
+
+/// An entry of `LEGACY_LOCATION_CACHE_FILE`.
+#[derive(Deserialize)]
+struct LegacyLocation {
+    location_id: String,
+    fetched_at: u64,
+}
+
+impl Storage {
+    /// Opens (or creates) the database and brings its schema up to date. A brand new
+    /// `data/housevaluation.db` imports the legacy CSV datasets and location cache if they
+    /// exist; other databases, like scratch ones for replays and tests, start empty.
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::open(path)?;
+        let storage = Self { conn };
+
+        let version = storage.migrate()?;
+        if version == 0 && is_default_database(path) {
+            storage.import_legacy_files()?;
+        }
+
+        Ok(storage)
+    }
+
+    /// Opens `DATABASE_PATH` if set (e.g. a scratch database for offline replays), else `data/housevaluation.db`.
+    pub fn open_default() -> Result<Self, Box<dyn Error>> {
+        Self::open(&default_path())
+    }
+
+    /// Runs pending migrations and returns the schema version found before migrating.
+    fn migrate(&self) -> Result<usize, Box<dyn Error>> {
+        let version: usize = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            self.conn.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            ))?;
+        }
+
+        Ok(version)
+    }
+
+    fn import_legacy_files(&self) -> Result<(), Box<dyn Error>> {
+        for country in Country::ALL {
+            for operation in [Operation::Sale, Operation::Rent] {
+                let dataset_path = country.dataset_path(operation);
+                if Path::new(&dataset_path).exists() {
+                    let imported = self.import_csv(country, operation, &dataset_path)?;
+                    println!("Imported {} listings from {}", imported, dataset_path);
+                }
+
+                let events = history::read_events(&country.history_path(operation))?;
+                self.insert_events(country, operation, &events)?;
+            }
+        }
+
+        if Path::new(LEGACY_LOCATION_CACHE_FILE).exists() {
+            let imported = self.import_location_cache(LEGACY_LOCATION_CACHE_FILE)?;
+            println!("Imported {} cached locations from {}", imported, LEGACY_LOCATION_CACHE_FILE);
+        }
+        Ok(())
+    }
+
+    /// Imports a legacy location cache file. It predates multi-country scraping, so every
+    /// entry is a Spanish location; entries keep their fetch time, and with it their TTL.
+    fn import_location_cache(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        let entries: HashMap<String, LegacyLocation> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        for (name, location) in &entries {
+            self.save_location(Country::Es, name, &location.location_id, location.fetched_at)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Upserts scraped properties into the listings of `country` and `operation` by property
+    /// code and stores the resulting lifecycle events as price history. New codes are
+    /// inserted, known ones get their fields and `last_seen` refreshed and are relisted.
+    ///
+    /// Each listing remembers the search in `coverage` that returned it. Active listings that
+    /// weren't returned this time are marked delisted, but only if the search that last
+    /// returned them is one of the complete scopes. Searches cut short by a page cap, a
+    /// failure or the quota returned only part of their results, so listings they missed
+    /// are left alone.
+    pub fn upsert_properties(
+        &self,
+        country: Country,
+        operation: Operation,
+        properties: &[Property],
+        coverage: &Coverage,
+    ) -> Result<UpsertSummary, Box<dyn Error>> {
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let event = |property_code: &str, kind, old_price, new_price| ListingEvent {
+            property_code: property_code.to_string(),
+            date: now.clone(),
+            kind,
+            old_price,
+            new_price,
+        };
+        let mut summary = UpsertSummary::default();
+
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut existing = tx.prepare(
+                "SELECT price_eur, delisted_at FROM listings WHERE country = ?1 AND operation = ?2 AND property_code = ?3",
+            )?;
+            let mut set_search = tx.prepare(
+                "UPDATE listings SET search = ?4 WHERE country = ?1 AND operation = ?2 AND property_code = ?3",
+            )?;
+            for property in properties {
+                let mut rows = existing.query(params![country.as_query(), operation.as_query(), property.property_code])?;
+                match rows.next()? {
+                    Some(row) => {
+                        let (old_price, delisted_at): (f64, Option<String>) = (row.get(0)?, row.get(1)?);
+                        if delisted_at.is_some() {
+                            summary.events.push(event(&property.property_code, EventKind::Relisted, None, Some(property.price_eur)));
+                        }
+                        if old_price != property.price_eur {
+                            summary.events.push(event(&property.property_code, EventKind::PriceChange, Some(old_price), Some(property.price_eur)));
+                        }
+                        summary.updated += 1;
+                    }
+                    None => {
+                        summary.events.push(event(&property.property_code, EventKind::Listed, None, Some(property.price_eur)));
+                        summary.inserted += 1;
+                    }
+                }
+
+                // Written right away, so a code repeated in `properties` is an update the second time.
+                let listing = StoredListing {
+                    property: property.clone(),
+                    first_seen: Some(now.clone()),
+                    last_seen: Some(now.clone()),
+                    delisted_at: None,
+                };
+                // Known listings keep the `first_seen` of their first scrape.
+                write_listings(&tx, country, operation, std::slice::from_ref(&listing), &["first_seen"])?;
+                if let Some(search) = coverage.searches.get(&property.property_code) {
+                    set_search.execute(params![country.as_query(), operation.as_query(), property.property_code, search])?;
+                }
+            }
+        }
+
+        let scraped_codes: Vec<&str> = properties.iter().map(|p| p.property_code.as_str()).collect();
+        let scraped_codes = serde_json::to_string(&scraped_codes)?;
+        {
+            let mut delist = tx.prepare(
+                "UPDATE listings SET delisted_at = ?1
+                 WHERE country = ?2 AND operation = ?3 AND search = ?4 AND property_type = ?5 AND delisted_at IS NULL
+                   AND property_code NOT IN (SELECT value FROM json_each(?6))
+                 RETURNING property_code, price_eur",
+            )?;
+            for (search, property_type) in &coverage.complete {
+                let delisted = delist.query_map(
+                    params![now, country.as_query(), operation.as_query(), search, property_type.as_query(), scraped_codes],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)),
+                )?;
+                for row in delisted {
+                    let (property_code, price) = row?;
+                    summary.events.push(event(&property_code, EventKind::Delisted, Some(price), None));
+                    summary.delisted += 1;
+                }
+            }
+        }
+
+        insert_events(&tx, country, operation, &summary.events)?;
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    pub fn listings(&self, country: Country, operation: Operation) -> Result<Vec<StoredListing>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM listings WHERE country = ?1 AND operation = ?2 ORDER BY rowid",
+            LISTING_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![country.as_query(), operation.as_query()], listing_from_row)?;
+
+        let mut listings = Vec::new();
+        for row in rows {
+            listings.push(row?);
+        }
+        Ok(listings)
+    }
+
+    /// Listing properties only, in the shape `Cervo` trains on.
+    pub fn properties(&self, country: Country, operation: Operation) -> Result<Vec<Property>, Box<dyn Error>> {
+        Ok(self.listings(country, operation)?.into_iter().map(|listing| listing.property).collect())
+    }
+
+    /// Listing properties of a single property type, for per-type models.
+    pub fn properties_of_type(&self, country: Country, operation: Operation, property_type: PropertyType) -> Result<Vec<Property>, Box<dyn Error>> {
+        let mut properties = self.properties(country, operation)?;
+        properties.retain(|property| property.property_type == property_type);
+        Ok(properties)
+    }
+
+    pub fn events(&self, country: Country, operation: Operation) -> Result<Vec<ListingEvent>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT property_code, date, event, old_price, new_price
+             FROM price_history WHERE country = ?1 AND operation = ?2 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![country.as_query(), operation.as_query()], |row| {
+            let kind: String = row.get(2)?;
+            Ok((row.get(0)?, row.get(1)?, kind, row.get(3)?, row.get(4)?))
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            let (property_code, date, kind, old_price, new_price) = row?;
+            let kind = EventKind::parse(&kind).ok_or_else(|| format!("Unknown event kind {}", kind))?;
+            events.push(ListingEvent { property_code, date, kind, old_price, new_price });
+        }
+        Ok(events)
+    }
+
+    /// Loads a dataset CSV into the listings of `country` and `operation`, deduplicating by property code.
+    pub fn import_csv(&self, country: Country, operation: Operation, csv_file_path: &str) -> Result<usize, Box<dyn Error>> {
+        let imported = dataset::read_listings(csv_file_path)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        write_listings(&tx, country, operation, &imported, &[])?;
+        tx.commit()?;
+        Ok(imported.len())
+    }
+
+    /// Writes the listings of `country` and `operation` to a dataset CSV, optionally only one property type.
+    pub fn export_csv(
+        &self,
+        country: Country,
+        operation: Operation,
+        property_type: Option<PropertyType>,
+        csv_file_path: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut listings = self.listings(country, operation)?;
+        if let Some(property_type) = property_type {
+            listings.retain(|listing| listing.property.property_type == property_type);
+        }
+        dataset::write_listings(csv_file_path, &listings)?;
+        Ok(listings.len())
+    }
+
+    pub fn location(&self, country: Country, name: &str) -> Result<Option<(String, u64)>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare("SELECT location_id, fetched_at FROM locations WHERE country = ?1 AND name = ?2")?;
+        let mut rows = stmt.query(params![country.as_query(), name])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_location(&self, country: Country, name: &str, location_id: &str, fetched_at: u64) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO locations (country, name, location_id, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+            params![country.as_query(), name, location_id, fetched_at],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes one cached location of `country`, or all of them when `name` is `None`.
+    pub fn delete_locations(&self, country: Country, name: Option<&str>) -> Result<usize, Box<dyn Error>> {
+        let removed = match name {
+            Some(name) => self.conn.execute(
+                "DELETE FROM locations WHERE country = ?1 AND name = ?2",
+                params![country.as_query(), name],
+            )?,
+            None => self.conn.execute("DELETE FROM locations WHERE country = ?1", params![country.as_query()])?,
+        };
+        Ok(removed)
+    }
+
+    pub fn record_model_run(&self, run: &ModelRun) -> Result<(), Box<dyn Error>> {
+        let (penalty, l1_ratio) = match run.hyperparameters {
+            Hyperparameters::ElasticNet { penalty, l1_ratio } => (Some(penalty), Some(l1_ratio)),
+            Hyperparameters::GradientBoosting(_) => (None, None),
+        };
+        self.conn.execute(
+            "INSERT INTO model_runs (country, operation, property_type, trained_at, model_path, samples, model_kind, target, metric, penalty, l1_ratio, hyperparameters, score)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                run.country.as_query(),
+                run.operation.as_query(),
+                run.property_type.as_query(),
+                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                run.model_path,
+                run.samples as i64,
+                run.hyperparameters.kind().as_query(),
+                run.target.as_query(),
+                run.metric.as_query(),
+                penalty,
+                l1_ratio,
+                serde_json::to_string(&run.hyperparameters)?,
+                run.score,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Next page to fetch and whether the location is finished, for an interrupted scrape.
+    pub fn scrape_progress(
+        &self,
+        country: Country,
+        operation: Operation,
+        location: &str,
+        property_type: &str,
+    ) -> Result<Option<Progress>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT next_page, done, complete FROM scrape_progress
+             WHERE country = ?1 AND operation = ?2 AND location = ?3 AND property_type = ?4",
+        )?;
+        let mut rows = stmt.query(params![country.as_query(), operation.as_query(), location, property_type])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Progress { next_page: row.get(0)?, done: row.get(1)?, complete: row.get(2)? })),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores one scraped page and the progress after it in a single transaction, so a
+    /// crash never leaves the checkpoint pointing past listings that weren't saved.
+    /// `search` is the configured location the page belongs to, `location` the band of it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn save_scrape_page(
+        &self,
+        country: Country,
+        operation: Operation,
+        search: &str,
+        location: &str,
+        property_type: &str,
+        progress: Progress,
+        properties: &[Property],
+    ) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO scrape_pending (country, operation, property_code, property, search)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for property in properties {
+                stmt.execute(params![
+                    country.as_query(),
+                    operation.as_query(),
+                    property.property_code,
+                    serde_json::to_string(property)?,
+                    search,
+                ])?;
+            }
+
+            tx.execute(
+                "INSERT OR REPLACE INTO scrape_progress (country, operation, location, property_type, next_page, done, complete, search)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    country.as_query(),
+                    operation.as_query(),
+                    location,
+                    property_type,
+                    progress.next_page,
+                    progress.done,
+                    progress.complete,
+                    search,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every property scraped since the checkpoint was last cleared.
+    pub fn scrape_pending(&self, country: Country, operation: Operation) -> Result<Vec<Property>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT property FROM scrape_pending WHERE country = ?1 AND operation = ?2 ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map(params![country.as_query(), operation.as_query()], |row| row.get::<_, String>(0))?;
+
+        let mut properties = Vec::new();
+        for row in rows {
+            properties.push(serde_json::from_str(&row?)?);
+        }
+        Ok(properties)
+    }
+
+    /// Pending properties with the search (configured location) each was scraped for.
+    pub fn scrape_pending_by_search(&self, country: Country, operation: Operation) -> Result<Vec<(String, Property)>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT search, property FROM scrape_pending WHERE country = ?1 AND operation = ?2 ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map(params![country.as_query(), operation.as_query()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut properties = Vec::new();
+        for row in rows {
+            let (search, property) = row?;
+            properties.push((search, serde_json::from_str(&property)?));
+        }
+        Ok(properties)
+    }
+
+    /// Searches and property types whose whole search, not just a band of it, finished
+    /// with every result it matched.
+    pub fn complete_searches(&self, country: Country, operation: Operation) -> Result<HashSet<Scope>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT search, property_type FROM scrape_progress
+             WHERE country = ?1 AND operation = ?2 AND location = search AND complete",
+        )?;
+        let rows = stmt.query_map(params![country.as_query(), operation.as_query()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut scopes = HashSet::new();
+        for row in rows {
+            let (search, property_type) = row?;
+            if let Some(property_type) = PropertyType::parse(&property_type) {
+                scopes.insert((search, property_type));
+            }
+        }
+        Ok(scopes)
+    }
+
+    pub fn has_scrape_checkpoint(&self, country: Country, operation: Operation) -> Result<bool, Box<dyn Error>> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM scrape_progress WHERE country = ?1 AND operation = ?2",
+            params![country.as_query(), operation.as_query()],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    pub fn clear_scrape_checkpoint(&self, country: Country, operation: Operation) -> Result<(), Box<dyn Error>> {
+        let market = params![country.as_query(), operation.as_query()];
+        self.conn.execute("DELETE FROM scrape_progress WHERE country = ?1 AND operation = ?2", market)?;
+        self.conn.execute("DELETE FROM scrape_pending WHERE country = ?1 AND operation = ?2", market)?;
+        Ok(())
+    }
+
+    fn insert_events(&self, country: Country, operation: Operation, events: &[ListingEvent]) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.unchecked_transaction()?;
+        insert_events(&tx, country, operation, events)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Inserts `listings`, or updates the stored row of a known property code in place.
+/// Columns in `keep` hold on to their stored value on update.
+fn write_listings(
+    conn: &Connection,
+    country: Country,
+    operation: Operation,
+    listings: &[StoredListing],
+    keep: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let updates: Vec<String> = LISTING_COLUMNS
+        .split(',')
+        .map(str::trim)
+        .filter(|column| *column != "property_code" && !keep.contains(column))
+        .map(|column| format!("{0} = excluded.{0}", column))
+        .collect();
+    let mut stmt = conn.prepare(&format!(
+        "INSERT INTO listings (country, operation, {}) VALUES
+         (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25,
+          ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34)
+         ON CONFLICT (country, operation, property_code) DO UPDATE SET {}",
+        LISTING_COLUMNS,
+        updates.join(", ")
+    ))?;
+
+    for listing in listings {
+        let p = &listing.property;
+        stmt.execute(params![
+            country.as_query(),
+            operation.as_query(),
+            p.property_code, p.price_eur, p.size_sqm, p.floor.code(), p.address, p.province,
+            p.municipality, p.district, p.neighborhood, p.latitude, p.longitude, p.has_lift,
+            p.price_per_sqm, p.rooms, p.bathrooms, p.swimming_pool, p.garden, p.garage, p.url,
+            listing.first_seen, listing.last_seen, listing.delisted_at, p.property_type.as_query(),
+            p.status, p.exterior, p.energy_rating, p.num_photos, p.description, p.has_parking_space,
+            p.parking_included, p.new_development, p.sub_typology,
+        ])?;
+    }
+    Ok(())
+}
+
+fn insert_events(conn: &Connection, country: Country, operation: Operation, events: &[ListingEvent]) -> Result<(), Box<dyn Error>> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO price_history (country, operation, property_code, date, event, old_price, new_price)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+
+    for event in events {
+        stmt.execute(params![
+            country.as_query(),
+            operation.as_query(),
+            event.property_code,
+            event.date,
+            event.kind.as_str(),
+            event.old_price,
+            event.new_price,
+        ])?;
+    }
+    Ok(())
+}
+
+fn listing_from_row(row: &Row) -> rusqlite::Result<StoredListing> {
+    Ok(StoredListing {
+        property: Property {
+            property_code: row.get(0)?,
+            price_eur: row.get(1)?,
+            size_sqm: row.get(2)?,
+            floor: Floor::from(row.get::<_, Option<String>>(3)?),
+            address: row.get(4)?,
+            province: row.get(5)?,
+            municipality: row.get(6)?,
+            district: row.get(7)?,
+            neighborhood: row.get(8)?,
+            latitude: row.get(9)?,
+            longitude: row.get(10)?,
+            has_lift: row.get(11)?,
+            price_per_sqm: row.get(12)?,
+            rooms: row.get(13)?,
+            bathrooms: row.get(14)?,
+            swimming_pool: row.get(15)?,
+            garden: row.get(16)?,
+            garage: row.get(17)?,
+            url: row.get(18)?,
+            property_type: PropertyType::parse(&row.get::<_, String>(22)?).unwrap_or_default(),
+            status: row.get(23)?,
+            exterior: row.get(24)?,
+            energy_rating: row.get(25)?,
+            num_photos: row.get(26)?,
+            description: row.get(27)?,
+            has_parking_space: row.get(28)?,
+            parking_included: row.get(29)?,
+            new_development: row.get(30)?,
+            sub_typology: row.get(31)?,
+        },
+        first_seen: row.get(19)?,
+        last_seen: row.get(20)?,
+        delisted_at: row.get(21)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(code: &str, price: f64, municipality: &str, property_type: PropertyType) -> Property {
+        Property {
+            property_code: code.to_string(),
+            price_eur: price,
+            size_sqm: Some(80.0),
+            floor: Floor::Level(2),
+            address: String::new(),
+            province: "Zaragoza".to_string(),
+            municipality: municipality.to_string(),
+            district: String::new(),
+            neighborhood: String::new(),
+            latitude: None,
+            longitude: None,
+            has_lift: None,
+            price_per_sqm: None,
+            rooms: None,
+            bathrooms: None,
+            swimming_pool: None,
+            garden: None,
+            garage: None,
+            url: String::new(),
+            property_type,
+            status: None,
+            exterior: None,
+            energy_rating: None,
+            num_photos: None,
+            description: None,
+            has_parking_space: None,
+            parking_included: None,
+            new_development: None,
+            sub_typology: None,
+        }
+    }
+
+    /// Every property returned by `search`, with `complete` the searches that finished.
+    fn coverage(search: &str, properties: &[Property], complete: &[(&str, PropertyType)]) -> Coverage {
+        Coverage {
+            searches: properties.iter().map(|p| (p.property_code.clone(), search.to_string())).collect(),
+            complete: complete.iter().map(|(search, property_type)| (search.to_string(), *property_type)).collect(),
+        }
+    }
+
+    fn kinds(summary: &UpsertSummary) -> Vec<EventKind> {
+        summary.events.iter().map(|event| event.kind).collect()
+    }
+
+    fn upsert(storage: &Storage, properties: &[Property], coverage: &Coverage) -> UpsertSummary {
+        storage.upsert_properties(Country::Es, Operation::Sale, properties, coverage).unwrap()
+    }
+
+    fn delisted(storage: &Storage) -> Vec<String> {
+        storage
+            .listings(Country::Es, Operation::Sale)
+            .unwrap()
+            .into_iter()
+            .filter(|listing| listing.delisted_at.is_some())
+            .map(|listing| listing.property.property_code)
+            .collect()
+    }
+
+    #[test]
+    fn inserts_new_codes_and_updates_known_ones() {
+        let storage = Storage::open(":memory:").unwrap();
+        let known = [property("1", 100_000.0, "Zaragoza", PropertyType::Homes)];
+        upsert(&storage, &known, &coverage("Zaragoza", &known, &[]));
+        let first_seen = storage.listings(Country::Es, Operation::Sale).unwrap()[0].first_seen.clone();
+
+        let scraped = [
+            property("1", 95_000.0, "Zaragoza", PropertyType::Homes),
+            property("2", 120_000.0, "Zaragoza", PropertyType::Homes),
+        ];
+        let summary = upsert(&storage, &scraped, &coverage("Zaragoza", &scraped, &[("Zaragoza", PropertyType::Homes)]));
+
+        assert_eq!((summary.inserted, summary.updated, summary.delisted), (1, 1, 0));
+        assert_eq!(kinds(&summary), vec![EventKind::PriceChange, EventKind::Listed]);
+        let listings = storage.listings(Country::Es, Operation::Sale).unwrap();
+        assert_eq!(listings.len(), 2);
+        assert_eq!(listings[0].property.price_eur, 95_000.0);
+        assert_eq!(listings[0].first_seen, first_seen);
+        assert!(listings[0].last_seen.is_some());
+        assert_eq!(storage.events(Country::Es, Operation::Sale).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn repeated_code_is_inserted_once() {
+        let storage = Storage::open(":memory:").unwrap();
+        let scraped = [
+            property("1", 100_000.0, "Zaragoza", PropertyType::Homes),
+            property("1", 100_000.0, "Zaragoza", PropertyType::Homes),
+        ];
+
+        let summary = upsert(&storage, &scraped, &Coverage::default());
+
+        assert_eq!((summary.inserted, summary.updated), (1, 1));
+        assert_eq!(storage.listings(Country::Es, Operation::Sale).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn delists_missing_listings_of_complete_searches_only() {
+        let storage = Storage::open(":memory:").unwrap();
+        let zaragoza = [
+            property("1", 100_000.0, "Zaragoza", PropertyType::Homes),
+            property("2", 100_000.0, "Zaragoza", PropertyType::Homes),
+        ];
+        let burgos = [property("3", 100_000.0, "Burgos", PropertyType::Homes)];
+        let garages = [property("4", 20_000.0, "Zaragoza", PropertyType::Garages)];
+        upsert(&storage, &zaragoza, &coverage("Zaragoza", &zaragoza, &[]));
+        upsert(&storage, &burgos, &coverage("Burgos", &burgos, &[]));
+        upsert(&storage, &garages, &coverage("Zaragoza", &garages, &[]));
+        let rent = [property("5", 900.0, "Zaragoza", PropertyType::Homes)];
+        storage
+            .upsert_properties(Country::Es, Operation::Rent, &rent, &coverage("Zaragoza", &rent, &[]))
+            .unwrap();
+
+        let scraped = [property("1", 100_000.0, "Zaragoza", PropertyType::Homes)];
+        let summary = upsert(&storage, &scraped, &coverage("Zaragoza", &scraped, &[("Zaragoza", PropertyType::Homes)]));
+
+        assert_eq!((summary.inserted, summary.updated, summary.delisted), (0, 1, 1));
+        assert_eq!(kinds(&summary), vec![EventKind::Delisted]);
+        assert_eq!(delisted(&storage), vec!["2"]);
+        assert!(storage.listings(Country::Es, Operation::Rent).unwrap()[0].delisted_at.is_none());
+    }
+
+    #[test]
+    fn complete_search_leaves_listings_of_other_searches_in_its_municipality() {
+        let storage = Storage::open(":memory:").unwrap();
+        let city = [property("1", 100_000.0, "Zaragoza", PropertyType::Homes)];
+        upsert(&storage, &city, &coverage("Zaragoza", &city, &[]));
+
+        // A narrower search of the same municipality finishes without returning listing 1.
+        let district = [property("2", 100_000.0, "Zaragoza", PropertyType::Homes)];
+        let summary = upsert(&storage, &district, &coverage("Casco Histórico", &district, &[("Casco Histórico", PropertyType::Homes)]));
+
+        assert_eq!(summary.delisted, 0);
+        assert!(delisted(&storage).is_empty());
+
+        // Listing 2 now belongs to the narrower search, which delists it once it's gone.
+        let summary = upsert(&storage, &[], &coverage("Casco Histórico", &[], &[("Casco Histórico", PropertyType::Homes)]));
+        assert_eq!(summary.delisted, 1);
+        assert_eq!(delisted(&storage), vec!["2"]);
+    }
+
+    #[test]
+    fn incomplete_scrape_delists_nothing() {
+        let storage = Storage::open(":memory:").unwrap();
+        let known = [
+            property("1", 100_000.0, "Zaragoza", PropertyType::Homes),
+            property("2", 100_000.0, "Zaragoza", PropertyType::Homes),
+        ];
+        upsert(&storage, &known, &coverage("Zaragoza", &known, &[]));
+
+        let summary = upsert(&storage, &known[..1], &coverage("Zaragoza", &known[..1], &[]));
+
+        assert_eq!((summary.inserted, summary.updated, summary.delisted), (0, 1, 0));
+        assert!(delisted(&storage).is_empty());
+    }
+
+    #[test]
+    fn relists_a_delisted_listing_that_reappears() {
+        let storage = Storage::open(":memory:").unwrap();
+        let scraped = [property("1", 100_000.0, "Zaragoza", PropertyType::Homes)];
+        let complete = coverage("Zaragoza", &scraped, &[("Zaragoza", PropertyType::Homes)]);
+        upsert(&storage, &scraped, &complete);
+
+        let summary = upsert(&storage, &[], &coverage("Zaragoza", &[], &[("Zaragoza", PropertyType::Homes)]));
+        assert_eq!(summary.delisted, 1);
+        assert_eq!(delisted(&storage), vec!["1"]);
+
+        let summary = upsert(&storage, &scraped, &complete);
+        assert_eq!((summary.inserted, summary.updated, summary.delisted), (0, 1, 0));
+        assert_eq!(kinds(&summary), vec![EventKind::Relisted]);
+        assert!(delisted(&storage).is_empty());
+    }
+}
@@ -1,48 +1,274 @@
-#[allow(snake_case)]
-use serde::Deserialize;
+#![allow(non_snake_case)]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// Market a listing belongs to. Sale and rent listings are kept in separate
+/// datasets and each gets its own `Cervo` model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Sale,
+    Rent,
+}
+
+impl Operation {
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            Operation::Sale => "sale",
+            Operation::Rent => "rent",
+        }
+    }
+
+    /// Dataset word used in file names: sale listings are "homes", rent listings "rentals".
+    fn dataset_name(&self) -> &'static str {
+        match self {
+            Operation::Sale => "homes",
+            Operation::Rent => "rentals",
+        }
+    }
+}
+
+/// Idealista country site. Every country has its own datasets and models, since
+/// prices and locations don't carry over between markets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Country {
+    #[default]
+    Es,
+    Pt,
+    It,
+}
+
+impl Country {
+    pub const ALL: [Country; 3] = [Country::Es, Country::Pt, Country::It];
+
+    /// Value of Idealista's `location` query parameter.
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            Country::Es => "es",
+            Country::Pt => "pt",
+            Country::It => "it",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Country::Es => "Spain",
+            Country::Pt => "Portugal",
+            Country::It => "Italy",
+        }
+    }
+
+    /// Language of the returned listings, Idealista's `locale` query parameter.
+    pub fn locale(&self) -> &'static str {
+        self.as_query()
+    }
+
+    /// e.g. `data/idealista_homes_spain.csv` or `data/idealista_rentals_portugal.csv`.
+    pub fn dataset_path(&self, operation: Operation) -> String {
+        format!("data/idealista_{}_{}.csv", operation.dataset_name(), self.label().to_lowercase())
+    }
+
+    pub fn history_path(&self, operation: Operation) -> String {
+        format!("data/idealista_{}_{}_history.csv", operation.dataset_name(), self.label().to_lowercase())
+    }
+
+    /// Model file for one country, market and property type. Spanish homes keep the
+    /// original `output/cervo_model.bin` / `output/cervo_rent_model.bin` names.
+    pub fn model_path(&self, operation: Operation, property_type: PropertyType) -> String {
+        match (self, property_type) {
+            (Country::Es, PropertyType::Homes) => match operation {
+                Operation::Sale => "output/cervo_model.bin".to_string(),
+                Operation::Rent => "output/cervo_rent_model.bin".to_string(),
+            },
+            (Country::Es, _) => format!("output/cervo_{}_{}_model.bin", property_type.as_query(), operation.as_query()),
+            _ => format!(
+                "output/cervo_{}_{}_{}_model.bin",
+                self.as_query(), property_type.as_query(), operation.as_query()
+            ),
+        }
+    }
+}
+
+/// Idealista search category. Each type is stored alongside the others but can be
+/// exported and modelled on its own, since a garage and a flat share few price drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PropertyType {
+    #[default]
+    Homes,
+    NewDevelopments,
+    Garages,
+    StorageRooms,
+    Lands,
+    Offices,
+    Premises,
+}
+
+impl PropertyType {
+    pub const ALL: [PropertyType; 7] = [
+        PropertyType::Homes,
+        PropertyType::NewDevelopments,
+        PropertyType::Garages,
+        PropertyType::StorageRooms,
+        PropertyType::Lands,
+        PropertyType::Offices,
+        PropertyType::Premises,
+    ];
+
+    /// Value of Idealista's `propertyType` query parameter.
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            PropertyType::Homes => "homes",
+            PropertyType::NewDevelopments => "newDevelopments",
+            PropertyType::Garages => "garages",
+            PropertyType::StorageRooms => "storageRooms",
+            PropertyType::Lands => "lands",
+            PropertyType::Offices => "offices",
+            PropertyType::Premises => "premises",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|property_type| property_type.as_query() == value)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PropertyType::Homes => "Homes",
+            PropertyType::NewDevelopments => "New developments",
+            PropertyType::Garages => "Garages / parking spaces",
+            PropertyType::StorageRooms => "Storage rooms",
+            PropertyType::Lands => "Land plots",
+            PropertyType::Offices => "Offices",
+            PropertyType::Premises => "Commercial premises",
+        }
+    }
+
+    /// Whether listings of this type have bedrooms and bathrooms. For those that don't,
+    /// missing counts are zero rather than unknown.
+    pub fn has_rooms(&self) -> bool {
+        matches!(self, PropertyType::Homes | PropertyType::NewDevelopments)
+    }
+}
+
+/// Floor of a listing, parsed from Idealista's floor codes. Stored and serialized as the
+/// original code (`bj`, `3`, ...), with no value for `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(from = "Option<String>", into = "Option<String>")]
+pub enum Floor {
+    /// `st`, sótano.
+    Basement,
+    /// `ss`, semisótano.
+    SemiBasement,
+    /// `bj`, bajo.
+    Ground,
+    /// `en`, entreplanta.
+    Mezzanine,
+    /// A numbered floor. Negative numbers are basement levels below `st`, e.g. `-2`.
+    Level(i32),
+    /// Missing, `N/A` or a code we don't recognise.
+    #[default]
+    Unknown,
+}
+
+impl Floor {
+    pub fn parse(code: &str) -> Self {
+        match code.trim().to_lowercase().as_str() {
+            "st" => Floor::Basement,
+            "ss" => Floor::SemiBasement,
+            "bj" | "0" => Floor::Ground,
+            "en" => Floor::Mezzanine,
+            other => match other.parse::<i32>() {
+                Ok(level) => Floor::Level(level),
+                Err(_) => Floor::Unknown,
+            },
+        }
+    }
+
+    /// Idealista code for the floor, `None` when unknown.
+    pub fn code(&self) -> Option<String> {
+        match self {
+            Floor::Basement => Some("st".to_string()),
+            Floor::SemiBasement => Some("ss".to_string()),
+            Floor::Ground => Some("bj".to_string()),
+            Floor::Mezzanine => Some("en".to_string()),
+            Floor::Level(level) => Some(level.to_string()),
+            Floor::Unknown => None,
+        }
+    }
+
+    /// Height in storeys relative to the street: basements are below 0, the
+    /// mezzanine sits halfway between the ground and first floor.
+    pub fn level(&self) -> Option<f64> {
+        match self {
+            Floor::Basement => Some(-1.0),
+            Floor::SemiBasement => Some(-0.5),
+            Floor::Ground => Some(0.0),
+            Floor::Mezzanine => Some(0.5),
+            Floor::Level(level) => Some(f64::from(*level)),
+            Floor::Unknown => None,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == Floor::Unknown
+    }
+}
+
+impl From<Option<String>> for Floor {
+    fn from(code: Option<String>) -> Self {
+        code.as_deref().map_or(Floor::Unknown, Floor::parse)
+    }
+}
+
+impl From<Floor> for Option<String> {
+    fn from(floor: Floor) -> Self {
+        floor.code()
+    }
+}
+
+/// A listing normalized across every scraper backend. Fields a portal may
+/// leave out are optional and written as `N/A` in the dataset CSV.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Property {
     pub property_code: String,
+    /// Sale price, or monthly rent for rental listings.
     pub price_eur: f64,
-    pub size_sqm: f64,
-    pub floor: Option<u32>,
+    pub size_sqm: Option<f64>,
+    #[serde(default)]
+    pub floor: Floor,
     pub address: String,
     pub province: String,
     pub municipality: String,
     pub district: String,
     pub neighborhood: String,
-    pub latitude: f64,
-    pub longitude: f64,
-    pub has_lift: bool,
-    pub price_per_sqm: f64,
-    pub rooms: u32,
-    pub bathrooms: u32,
-    pub swimming_pool: bool,
-    pub garden: bool,
-    pub garage: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub has_lift: Option<bool>,
+    pub price_per_sqm: Option<f64>,
+    pub rooms: Option<u32>,
+    pub bathrooms: Option<u32>,
+    pub swimming_pool: Option<bool>,
+    pub garden: Option<bool>,
+    pub garage: Option<bool>,
     pub url: String,
+    #[serde(default)]
+    pub property_type: PropertyType,
+    /// Idealista condition code: `good`, `renew` or `newdevelopment`.
+    pub status: Option<String>,
+    pub exterior: Option<bool>,
+    /// Energy consumption rating, `a` (best) to `g`.
+    pub energy_rating: Option<String>,
+    pub num_photos: Option<u32>,
+    pub description: Option<String>,
+    pub has_parking_space: Option<bool>,
+    /// Whether the parking space is included in the price rather than sold separately.
+    pub parking_included: Option<bool>,
+    pub new_development: Option<bool>,
+    /// Idealista's detailed subtype, e.g. `penthouse`, `duplex` or `studio`.
+    pub sub_typology: Option<String>,
 }
 
-impl Property {
-    pub(crate) fn to_feature_vector(&self) -> Vec<f64> {
-        vec![
-            self.size_sqm,
-            self.floor.unwrap_or(0) as f64,
-            self.latitude,
-            self.longitude,
-            if self.has_lift { 1.0 } else { 0.0 },
-            self.price_per_sqm,
-            self.rooms as f64,
-            self.bathrooms as f64,
-            if self.swimming_pool { 1.0 } else { 0.0 },
-            if self.garden { 1.0 } else { 0.0 },
-            if self.garage { 1.0 } else { 0.0 },
-        ]
-    }
-}
-
-
 #[derive(Debug, Deserialize)]
 pub struct Suggestion {
     pub name: String,
@@ -82,6 +308,37 @@ pub struct HomeListing {
     pub garden: Option<bool>,
     pub garage: Option<bool>,
     pub url: Option<String>,
+    pub status: Option<String>,
+    pub exterior: Option<bool>,
+    pub energyCertification: Option<EnergyCertification>,
+    pub numPhotos: Option<u32>,
+    pub description: Option<String>,
+    pub parkingSpace: Option<ParkingSpace>,
+    pub newDevelopment: Option<bool>,
+    pub detailedType: Option<DetailedType>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DetailedType {
+    pub typology: Option<String>,
+    pub subTypology: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnergyCertification {
+    pub energyConsumption: Option<EnergyRating>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EnergyRating {
+    #[serde(rename = "type")]
+    pub rating: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParkingSpace {
+    pub hasParkingSpace: Option<bool>,
+    pub isParkingSpaceIncludedInPrice: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,3 +346,63 @@ pub struct ListingsResponse {
     pub elementList: Vec<HomeListing>,
     pub totalPages: u32,
 }
+
+impl From<HomeListing> for Property {
+    fn from(home: HomeListing) -> Self {
+        Self {
+            property_code: home.propertyCode,
+            price_eur: home.price,
+            size_sqm: home.size,
+            floor: Floor::from(home.floor),
+            address: home.address.unwrap_or_default(),
+            province: home.province.unwrap_or_default(),
+            municipality: home.municipality.unwrap_or_default(),
+            district: home.district.unwrap_or_default(),
+            neighborhood: home.neighborhood.unwrap_or_default(),
+            latitude: home.latitude,
+            longitude: home.longitude,
+            has_lift: home.hasLift,
+            price_per_sqm: home.priceByArea,
+            rooms: home.rooms.map(|r| r as u32),
+            bathrooms: home.bathrooms.map(|b| b as u32),
+            swimming_pool: home.swimmingPool,
+            garden: home.garden,
+            garage: home.garage,
+            url: home.url.unwrap_or_default(),
+            property_type: PropertyType::default(),
+            status: home.status,
+            exterior: home.exterior,
+            energy_rating: home
+                .energyCertification
+                .and_then(|certification| certification.energyConsumption)
+                .and_then(|consumption| consumption.rating)
+                .map(|rating| rating.to_lowercase()),
+            num_photos: home.numPhotos,
+            description: home.description,
+            has_parking_space: home.parkingSpace.as_ref().and_then(|parking| parking.hasParkingSpace),
+            parking_included: home.parkingSpace.as_ref().and_then(|parking| parking.isParkingSpaceIncludedInPrice),
+            new_development: home.newDevelopment,
+            sub_typology: home.detailedType.and_then(|detailed| detailed.subTypology),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floor_codes_round_trip() {
+        for code in ["st", "ss", "bj", "en", "1", "7", "-1", "-2"] {
+            assert_eq!(Floor::parse(code).code().as_deref(), Some(code));
+        }
+    }
+
+    #[test]
+    fn negative_floors_keep_their_level() {
+        assert_eq!(Floor::parse("-2"), Floor::Level(-2));
+        assert_eq!(Floor::parse("-2").level(), Some(-2.0));
+        assert_eq!(Floor::parse("0"), Floor::Ground);
+        assert!(Floor::parse("N/A").is_unknown());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::core::cervo::Cervo;
+use crate::core::types::Property;
+
+/// Yearly running costs and purchase overheads used for net yield and cap rate.
+#[derive(Debug, Clone)]
+pub struct OperatingCosts {
+    /// Annual IBI (property tax) in euros.
+    pub ibi_annual: f64,
+    /// Monthly community fees in euros.
+    pub community_fees_monthly: f64,
+    /// Annual insurance premium in euros.
+    pub insurance_annual: f64,
+    /// Share of the year the property is expected to sit empty (0.05 = 5%).
+    pub vacancy_rate: f64,
+    /// Taxes, notary and agency fees on purchase as a share of the price (0.10 = 10%).
+    pub purchase_costs_rate: f64,
+}
+
+impl Default for OperatingCosts {
+    fn default() -> Self {
+        Self {
+            ibi_annual: 400.0,
+            community_fees_monthly: 60.0,
+            insurance_annual: 250.0,
+            vacancy_rate: 0.05,
+            purchase_costs_rate: 0.10,
+        }
+    }
+}
+
+impl OperatingCosts {
+    /// Reads costs from `YIELD_*` env vars, falling back to the defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |key: &str, default: f64| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            ibi_annual: var("YIELD_IBI_ANNUAL", defaults.ibi_annual),
+            community_fees_monthly: var("YIELD_COMMUNITY_FEES_MONTHLY", defaults.community_fees_monthly),
+            insurance_annual: var("YIELD_INSURANCE_ANNUAL", defaults.insurance_annual),
+            vacancy_rate: var("YIELD_VACANCY_RATE", defaults.vacancy_rate),
+            purchase_costs_rate: var("YIELD_PURCHASE_COSTS_RATE", defaults.purchase_costs_rate),
+        }
+    }
+
+    pub fn annual_running_costs(&self) -> f64 {
+        self.ibi_annual + self.community_fees_monthly * 12.0 + self.insurance_annual
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct YieldReport {
+    pub sale_price: f64,
+    pub monthly_rent: f64,
+    pub annual_rent: f64,
+    /// Annual rent after vacancy minus running costs.
+    pub net_operating_income: f64,
+    /// Annual rent over sale price.
+    pub gross_yield: f64,
+    /// Net operating income over sale price plus purchase costs.
+    pub net_yield: f64,
+    /// Net operating income over sale price.
+    pub cap_rate: f64,
+}
+
+/// Values `property` with the sale and rent models and derives its yields.
+pub fn yield_report(sale_model: &Cervo, rent_model: &Cervo, property: &Property, costs: &OperatingCosts) -> YieldReport {
+    let sale_price = sale_model.predict_price(property);
+    let monthly_rent = rent_model.predict_price(property);
+
+    compute_yields(sale_price, monthly_rent, costs)
+}
+
+/// Derives yields from a known sale price and monthly rent.
+pub fn compute_yields(sale_price: f64, monthly_rent: f64, costs: &OperatingCosts) -> YieldReport {
+    let annual_rent = monthly_rent * 12.0;
+    let net_operating_income = annual_rent * (1.0 - costs.vacancy_rate) - costs.annual_running_costs();
+    let total_investment = sale_price * (1.0 + costs.purchase_costs_rate);
+
+    let ratio = |numerator: f64, denominator: f64| {
+        if denominator > 0.0 { numerator / denominator } else { 0.0 }
+    };
+
+    YieldReport {
+        sale_price,
+        monthly_rent,
+        annual_rent,
+        net_operating_income,
+        gross_yield: ratio(annual_rent, sale_price),
+        net_yield: ratio(net_operating_income, total_investment),
+        cap_rate: ratio(net_operating_income, sale_price),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn computes_gross_and_net_yields_and_cap_rate() {
+        let report = compute_yields(200_000.0, 1_000.0, &OperatingCosts::default());
+
+        // 12,000 rent, 5% vacancy, 400 IBI + 12 × 60 fees + 250 insurance.
+        assert!(close(report.annual_rent, 12_000.0));
+        assert!(close(report.net_operating_income, 11_400.0 - 1_370.0));
+        assert!(close(report.gross_yield, 0.06));
+        assert!(close(report.net_yield, 10_030.0 / 220_000.0));
+        assert!(close(report.cap_rate, 10_030.0 / 200_000.0));
+    }
+
+    #[test]
+    fn zero_price_gives_zero_ratios() {
+        let report = compute_yields(0.0, 1_000.0, &OperatingCosts::default());
+
+        assert_eq!((report.gross_yield, report.net_yield, report.cap_rate), (0.0, 0.0, 0.0));
+        assert!(close(report.net_operating_income, 10_030.0));
+    }
+}
@@ -6,15 +6,34 @@ use std::fs;
 
 use dialoguer::{Select, Input, Confirm};
 
-use core::types::Property;
+use core::types::{Country, Floor, Operation, Property, PropertyType};
+use core::history;
+use core::storage::Storage;
+use core::scrapers::Scraper;
+use core::scrapers::checkpoint::ScrapeCheckpoint;
+use core::scrapers::feed::FeedScraper;
 use core::scrapers::idealista::IdealistaScraper;
+use core::scrapers::location_cache::LocationCache;
+use core::scrapers::replay::ResponseMode;
 use core::cervo::Cervo;
+use core::config::ScrapeConfig;
+use core::yields::{self, OperatingCosts};
 
 fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
 
     loop {
-        let options = &["Scrape Data", "Predict Property Price", "Train Model (Make sure to have new data)", "Exit"];
+        let options = &[
+            "Scrape Data",
+            "Predict Property Price",
+            "Predict Monthly Rent",
+            "Rental Yield Report",
+            "Train Model (Make sure to have new data)",
+            "Market Activity Report",
+            "Clear Location Cache",
+            "Import / Export CSV",
+            "Exit",
+        ];
         let selection = Select::new()
             .with_prompt("What do you want to do?")
             .items(options)
@@ -23,19 +42,85 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         match selection {
             0 => {
-                let mut scraper = IdealistaScraper::new();
-                println!("Starting scraping process... (this may take a while, please be patient)");
-                scraper.scrape_all_homes_spain()?;
-                println!("Scraping completed, output saved to data/idealista_homes_spain.csv.");
+                let country = select_country()?;
+                let operation = select_operation()?;
+                // Picked first so a replay fails before anything opens the default database.
+                let mut scraper = select_scraper(country, operation)?;
+
+                let checkpoint = ScrapeCheckpoint::open(country, operation)?;
+                if checkpoint.exists()? {
+                    let resume = Confirm::new()
+                        .with_prompt("An interrupted scrape was found. Resume it?")
+                        .default(true)
+                        .interact()?;
+                    if !resume {
+                        checkpoint.clear()?;
+                    }
+                }
+
+                println!("Starting scraping process with {}... (this may take a while, please be patient)", scraper.name());
+                let properties = scraper.scrape()?;
+                if let Some(summary) = scraper.summary() {
+                    println!("{}", summary);
+                }
+                let summary = Storage::open_default()?.upsert_properties(country, operation, &properties, &scraper.coverage()?)?;
+                println!(
+                    "Scraping completed, {} new, {} updated and {} delisted properties saved to the database.",
+                    summary.inserted, summary.updated, summary.delisted
+                );
+
+                // The checkpoint is what a resumed run continues from, so it's only dropped
+                // once every search is done and no request failed along the way.
+                let failures = scraper.summary().map_or(0, |summary| summary.failures.len());
+                let unfinished = scraper.unfinished_searches();
+                if unfinished.is_empty() && failures == 0 {
+                    checkpoint.clear()?;
+                } else {
+                    if !unfinished.is_empty() {
+                        println!("Unfinished searches: {}", unfinished.join(", "));
+                    }
+                    println!("The scrape didn't finish ({} failed requests). Scrape again and resume to continue it.", failures);
+                }
             }
             1 => {
-                let property = build_property_from_user_input();
-                let cervo = Cervo::new("data/idealista_homes_spain.csv")?;
+                let country = select_country()?;
+                let property_type = select_property_type()?;
+                let property = build_property_from_user_input(property_type);
+                let cervo = Cervo::new(country, Operation::Sale, property_type)?;
                 let predicted_price = cervo.predict_price(&property);
 
                 println!("💰 Predicted price: €{:.2}", predicted_price);
             }
             2 => {
+                let country = select_country()?;
+                let property_type = select_property_type()?;
+                let property = build_property_from_user_input(property_type);
+                let cervo = Cervo::new(country, Operation::Rent, property_type)?;
+                let predicted_rent = cervo.predict_price(&property);
+
+                println!("🏠 Predicted monthly rent: €{:.2}", predicted_rent);
+            }
+            3 => {
+                let country = select_country()?;
+                let property_type = select_property_type()?;
+                let property = build_property_from_user_input(property_type);
+                let costs = build_operating_costs_from_user_input();
+                let sale_model = Cervo::new(country, Operation::Sale, property_type)?;
+                let rent_model = Cervo::new(country, Operation::Rent, property_type)?;
+                let report = yields::yield_report(&sale_model, &rent_model, &property, &costs);
+
+                println!("💰 Predicted price: €{:.2}", report.sale_price);
+                println!("🏠 Predicted monthly rent: €{:.2}", report.monthly_rent);
+                println!("Net operating income: €{:.2}/year", report.net_operating_income);
+                println!("Gross yield: {:.2}%", report.gross_yield * 100.0);
+                println!("Net yield: {:.2}%", report.net_yield * 100.0);
+                println!("Cap rate: {:.2}%", report.cap_rate * 100.0);
+            }
+            4 => {
+                let country = select_country()?;
+                let operation = select_operation()?;
+                let property_type = select_property_type()?;
+
                 let k_folds = std::env::var("K_FOLDS")
                     .ok()
                     .and_then(|v| v.parse::<usize>().ok())
@@ -53,7 +138,66 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 update_env("K_FOLDS", &new_k_folds.to_string());
 
-                Cervo::train_and_save_model("data/idealista_homes_spain.csv")?;
+                Cervo::train_and_save_model(country, operation, property_type)?;
+            }
+            5 => {
+                let country = select_country()?;
+                let operation = select_operation()?;
+                let storage = Storage::open_default()?;
+                let listings = storage.listings(country, operation)?;
+                let events = storage.events(country, operation)?;
+
+                for city in history::market_activity(&listings, &events) {
+                    println!(
+                        "{}: {} listings, {} delisted, {:.1} days on market, {} price cuts (avg {:.1}%)",
+                        city.city, city.listings, city.delisted, city.avg_days_on_market,
+                        city.price_cuts, city.avg_price_cut * 100.0
+                    );
+                }
+            }
+            6 => {
+                let country = select_country()?;
+                let city: String = Input::new()
+                    .with_prompt("City to invalidate (leave empty to clear every city of the country)")
+                    .allow_empty(true)
+                    .interact_text()?;
+
+                let cache = LocationCache::from_env()?;
+                let removed = cache.invalidate(country, Some(city.trim()).filter(|c| !c.is_empty()))?;
+                println!("Removed {} cached location(s).", removed);
+            }
+            7 => {
+                let country = select_country()?;
+                let operation = select_operation()?;
+                let actions = &["Import CSV into database", "Export database to CSV"];
+                let action = Select::new()
+                    .with_prompt("What do you want to do?")
+                    .items(actions)
+                    .default(0)
+                    .interact()?;
+
+                let path: String = Input::new()
+                    .with_prompt("CSV path")
+                    .default(country.dataset_path(operation))
+                    .interact_text()?;
+
+                let storage = Storage::open_default()?;
+                if action == 0 {
+                    let imported = storage.import_csv(country, operation, &path)?;
+                    println!("Imported {} listings from {}.", imported, path);
+                } else {
+                    let mut types = vec!["All property types"];
+                    types.extend(PropertyType::ALL.iter().map(PropertyType::label));
+                    let selection = Select::new()
+                        .with_prompt("Which property types?")
+                        .items(&types)
+                        .default(0)
+                        .interact()?;
+                    let property_type = selection.checked_sub(1).map(|i| PropertyType::ALL[i]);
+
+                    let exported = storage.export_csv(country, operation, property_type, &path)?;
+                    println!("Exported {} listings to {}.", exported, path);
+                }
             }
             _ => {
                 println!("👋 Exiting.");
@@ -63,18 +207,92 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
-fn build_property_from_user_input() -> Property {
+fn select_operation() -> Result<Operation, Box<dyn Error>> {
+    let markets = &["Sale", "Rent"];
+    let selection = Select::new()
+        .with_prompt("Which market?")
+        .items(markets)
+        .default(0)
+        .interact()?;
+
+    Ok(if selection == 0 { Operation::Sale } else { Operation::Rent })
+}
+
+fn select_country() -> Result<Country, Box<dyn Error>> {
+    let labels: Vec<&str> = Country::ALL.iter().map(Country::label).collect();
+    let selection = Select::new()
+        .with_prompt("Which country?")
+        .items(&labels)
+        .default(0)
+        .interact()?;
+
+    Ok(Country::ALL[selection])
+}
+
+fn select_property_type() -> Result<PropertyType, Box<dyn Error>> {
+    let labels: Vec<&str> = PropertyType::ALL.iter().map(PropertyType::label).collect();
+    let selection = Select::new()
+        .with_prompt("Which property type?")
+        .items(&labels)
+        .default(0)
+        .interact()?;
+
+    Ok(PropertyType::ALL[selection])
+}
+
+fn select_scraper(country: Country, operation: Operation) -> Result<Box<dyn Scraper>, Box<dyn Error>> {
+    let sources = &["Idealista", "Idealista (replay recorded responses)", "In-house feed (JSON)"];
+    let selection = Select::new()
+        .with_prompt("Which source do you want to scrape?")
+        .items(sources)
+        .default(0)
+        .interact()?;
+
+    match selection {
+        0 => Ok(Box::new(IdealistaScraper::new(country, operation, ScrapeConfig::from_env()?)?)),
+        1 => {
+            let default_dir = std::env::var("IDEALISTA_REPLAY_DIR").unwrap_or_else(|_| "data/fixtures/idealista".to_string());
+            let dir: String = Input::new()
+                .with_prompt("Directory with recorded responses")
+                .default(default_dir)
+                .interact_text()?;
+            let config_path: String = Input::new()
+                .with_prompt("Scrape config to replay")
+                .default(format!("{}/scrape_config.toml", dir))
+                .interact_text()?;
+
+            let config = ScrapeConfig::load(&config_path)?;
+            Ok(Box::new(IdealistaScraper::with_response_mode(country, operation, config, ResponseMode::Replay(dir.into()))?))
+        }
+        _ => {
+            let default_path = std::env::var("FEED_PATH").unwrap_or_else(|_| "data/feed.json".to_string());
+            let path: String = Input::new()
+                .with_prompt("Path to the feed file")
+                .default(default_path)
+                .interact_text()?;
+
+            Ok(Box::new(FeedScraper::new(&path)))
+        }
+    }
+}
+
+fn build_property_from_user_input(property_type: PropertyType) -> Property {
     let size_sqm: f64 = Input::new()
         .with_prompt("Enter the size (m²) of the property")
         .interact_text()
         .unwrap();
 
-    let floor: Option<u32> = Input::new()
-        .with_prompt("Enter the floor number (leave empty if not applicable)")
+    let floor = Input::new()
+        .with_prompt("Enter the floor: a number, bj (ground), en (mezzanine), ss (semi-basement) or st (basement); leave empty if not applicable")
         .allow_empty(true)
         .interact_text()
-        .ok()
-        .and_then(|input: String| input.parse().ok());
+        .map_or(Floor::Unknown, |input: String| Floor::parse(&input));
+
+    let top_floor = Confirm::new()
+        .with_prompt("Is it the top floor (ático)?")
+        .default(false)
+        .interact()
+        .unwrap();
 
     let latitude: f64 = Input::new()
         .with_prompt("Enter the latitude")
@@ -86,27 +304,40 @@ fn build_property_from_user_input() -> Property {
         .interact_text()
         .unwrap();
 
+    // Names as Idealista spells them; they pick the area price per m² the model starts from.
+    let location_name = |prompt: &str| -> String {
+        Input::new()
+            .with_prompt(prompt)
+            .allow_empty(true)
+            .interact_text()
+            .map(|input: String| input.trim().to_string())
+            .unwrap_or_default()
+    };
+    let province = location_name("Province (leave empty if unknown)");
+    let municipality = location_name("Municipality (e.g. Madrid)");
+    let district = location_name("District (leave empty if unknown)");
+    let neighborhood = location_name("Neighbourhood (leave empty if unknown)");
+
     let has_lift = Confirm::new()
         .with_prompt("Does the property have a lift?")
         .interact()
         .unwrap();
 
-    let price_per_sqm: Option<f64> = Input::new()
-        .with_prompt("Enter price per m² (if not known, leave empty)")
-        .allow_empty(true)
-        .interact_text()
-        .ok()
-        .and_then(|input: String| input.parse().ok());
+    let (rooms, bathrooms) = if property_type.has_rooms() {
+        let rooms: u32 = Input::new()
+            .with_prompt("Number of bedrooms")
+            .interact_text()
+            .unwrap();
 
-    let rooms: u32 = Input::new()
-        .with_prompt("Number of bedrooms")
-        .interact_text()
-        .unwrap();
+        let bathrooms: u32 = Input::new()
+            .with_prompt("Number of bathrooms")
+            .interact_text()
+            .unwrap();
 
-    let bathrooms: u32 = Input::new()
-        .with_prompt("Number of bathrooms")
-        .interact_text()
-        .unwrap();
+        (Some(rooms), Some(bathrooms))
+    } else {
+        (None, None)
+    };
 
     let swimming_pool = Confirm::new()
         .with_prompt("Does the property have a swimming pool?")
@@ -123,26 +354,85 @@ fn build_property_from_user_input() -> Property {
         .interact()
         .unwrap();
 
+    // Only asked when a model may use them (see MODEL_EXTRA_FEATURES).
+    let asks_extras = std::env::var("MODEL_EXTRA_FEATURES").is_ok_and(|v| !v.trim().is_empty());
+    let optional_text = |prompt: &str| -> Option<String> {
+        Input::new()
+            .with_prompt(prompt)
+            .allow_empty(true)
+            .interact_text()
+            .ok()
+            .map(|input: String| input.trim().to_lowercase())
+            .filter(|input| !input.is_empty())
+    };
+    let optional_flag = |prompt: &str| Confirm::new().with_prompt(prompt).interact().ok();
+
+    let (status, exterior, energy_rating, has_parking_space) = if asks_extras {
+        (
+            optional_text("Condition: good, renew or newdevelopment (leave empty if unknown)"),
+            optional_flag("Is the property exterior?"),
+            optional_text("Energy rating a-g (leave empty if unknown)"),
+            optional_flag("Does it come with a parking space?"),
+        )
+    } else {
+        (None, None, None, None)
+    };
+    // Only meaningful when there is a space; unknown otherwise rather than guessed.
+    let parking_included = if has_parking_space == Some(true) {
+        optional_flag("Is the parking space included in the price?")
+    } else {
+        None
+    };
+
     Property {
         property_code: "".to_string(),
         price_eur: 0.0,
-        size_sqm,
+        size_sqm: Some(size_sqm),
         floor,
         address: "".to_string(),
-        province: "".to_string(),
-        municipality: "".to_string(),
-        district: "".to_string(),
-        neighborhood: "".to_string(),
-        latitude,
-        longitude,
-        has_lift,
-        price_per_sqm: price_per_sqm.unwrap_or(0.0),
+        province,
+        municipality,
+        district,
+        neighborhood,
+        latitude: Some(latitude),
+        longitude: Some(longitude),
+        has_lift: Some(has_lift),
+        price_per_sqm: None,
         rooms,
         bathrooms,
-        swimming_pool,
-        garden,
-        garage,
+        swimming_pool: Some(swimming_pool),
+        garden: Some(garden),
+        garage: Some(garage),
         url: "".to_string(),
+        property_type,
+        new_development: status.as_deref().map(|s| s == "newdevelopment"),
+        status,
+        exterior,
+        energy_rating,
+        num_photos: None,
+        description: None,
+        parking_included,
+        has_parking_space,
+        sub_typology: top_floor.then(|| "penthouse".to_string()),
+    }
+}
+
+fn build_operating_costs_from_user_input() -> OperatingCosts {
+    let defaults = OperatingCosts::from_env();
+    let prompt = |text: &str, default: f64| -> f64 {
+        Input::new()
+            .with_prompt(text)
+            .default(default)
+            .interact_text()
+            .unwrap_or(default)
+    };
+
+    OperatingCosts {
+        ibi_annual: prompt("Annual IBI (€)", defaults.ibi_annual),
+        community_fees_monthly: prompt("Monthly community fees (€)", defaults.community_fees_monthly),
+        insurance_annual: prompt("Annual insurance (€)", defaults.insurance_annual),
+        vacancy_rate: prompt("Vacancy rate (0.05 = 5%)", defaults.vacancy_rate),
+        purchase_costs_rate: prompt("Purchase costs as share of price (0.10 = 10%)", defaults.purchase_costs_rate),
     }
 }
 
@@ -0,0 +1,40 @@
+//! A `mock_idealista` process for the integration tests that talk HTTP.
+
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// A `mock_idealista` process serving the fixtures, killed when dropped.
+pub struct MockServer {
+    process: Child,
+    /// Kept open so the server can keep logging requests.
+    _stdout: BufReader<ChildStdout>,
+    pub base_url: String,
+}
+
+impl MockServer {
+    pub fn start(flags: &[&str]) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut process = Command::new(env!("CARGO_BIN_EXE_mock_idealista"))
+            .args(["--port", &port.to_string(), "--fixtures", "data/fixtures/idealista"])
+            .args(flags)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // The server prints a line once it's listening.
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        assert!(line.contains("listening"), "mock server didn't start: {}", line);
+
+        Self { process, _stdout: stdout, base_url: format!("http://127.0.0.1:{}", port) }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
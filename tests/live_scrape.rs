@@ -0,0 +1,65 @@
+//! Runs a full live scrape against `mock_idealista`, configured the way a deployment
+//! would be: `api.base_url`, the API key header and extra `[api.headers]`.
+
+mod common;
+
+use common::MockServer;
+use housevaluation::core::config::ScrapeConfig;
+use housevaluation::core::scrapers::Scraper;
+use housevaluation::core::scrapers::idealista::IdealistaScraper;
+use housevaluation::core::scrapers::replay::ResponseMode;
+use housevaluation::core::types::{Country, Operation};
+
+use std::collections::HashSet;
+use std::fs;
+
+const API_KEY: &str = "test-key";
+
+#[test]
+fn scrapes_the_mock_api_with_the_configured_key() {
+    let server = MockServer::start(&["--api-key", API_KEY]);
+    let database = std::env::temp_dir().join(format!("housevaluation-live-{}.db", std::process::id()));
+    let _ = fs::remove_file(&database);
+
+    // SAFETY: the only test in this binary, so no other thread reads the environment.
+    unsafe {
+        std::env::set_var("DATABASE_PATH", &database);
+        std::env::set_var("MOCK_IDEALISTA_KEY", API_KEY);
+        std::env::set_var("REQUESTS_PER_SECOND", "100");
+    }
+
+    let config = format!(
+        r#"
+        [[locations]]
+        name = "Zaragoza"
+
+        [[locations]]
+        name = "Burgos"
+
+        [api]
+        base_url = "{}/"
+        api_key_header = "x-rapidapi-key"
+        api_key_env = "MOCK_IDEALISTA_KEY"
+
+        [api.headers]
+        x-rapidapi-host = "localhost"
+        "#,
+        server.base_url
+    );
+    let config: ScrapeConfig = toml::from_str(&config).unwrap();
+    let mut scraper = IdealistaScraper::with_response_mode(Country::Es, Operation::Sale, config, ResponseMode::Live).unwrap();
+
+    let properties = scraper.scrape().unwrap();
+
+    let codes: HashSet<&str> = properties.iter().map(|p| p.property_code.as_str()).collect();
+    assert_eq!(properties.len(), 90);
+    assert_eq!(codes.len(), 90);
+    let summary = scraper.summary().unwrap();
+    // Two location lookups, then one page of Burgos and two of Zaragoza; the mock rejects
+    // any request without the key with a 403.
+    assert_eq!(summary.requests, 5);
+    assert!(summary.failures.is_empty(), "{}", summary);
+    assert!(scraper.unfinished_searches().is_empty());
+
+    let _ = fs::remove_file(&database);
+}
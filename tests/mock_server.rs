@@ -0,0 +1,105 @@
+//! Runs the request layer against `mock_idealista` to check retries, `Retry-After` and
+//! RapidAPI quota handling over real HTTP.
+
+mod common;
+
+use common::MockServer;
+use housevaluation::core::scrapers::request::{RequestError, RequestLayer, RequestPolicy};
+
+use std::time::{Duration, Instant};
+
+/// The recorded `getsuggestions` response every test requests.
+fn suggestions_url(server: &MockServer) -> String {
+    format!("{}/getsuggestions?prefix=Zaragoza&location=es&operation=sale", server.base_url)
+}
+
+fn policy(max_retries: u32) -> RequestPolicy {
+    RequestPolicy {
+        requests_per_second: 100.0,
+        max_retries,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(100),
+        quota_reserve: 0,
+    }
+}
+
+fn layer(max_retries: u32) -> RequestLayer {
+    RequestLayer::new(policy(max_retries))
+}
+
+#[test]
+fn retries_server_errors() {
+    let server = MockServer::start(&["--error-every", "2"]);
+    let mut requests = layer(3);
+
+    for _ in 0..2 {
+        let body = requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+        assert!(body.contains("Zaragoza"));
+    }
+
+    let summary = requests.summary();
+    assert_eq!(summary.requests, 3);
+    assert_eq!(summary.retries, 1);
+    assert!(summary.failures.is_empty());
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+    let server = MockServer::start(&["--error-every", "1"]);
+    let mut requests = layer(2);
+
+    let result = requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza");
+
+    assert!(matches!(result, Err(RequestError::Status(503))));
+    let summary = requests.summary();
+    assert_eq!(summary.requests, 3);
+    assert_eq!(summary.retries, 2);
+    assert_eq!(summary.failures.len(), 1);
+}
+
+#[test]
+fn waits_for_retry_after_when_throttled() {
+    let server = MockServer::start(&["--throttle-every", "2"]);
+    let mut requests = RequestLayer::new(RequestPolicy { max_backoff: Duration::from_secs(2), ..policy(3) });
+
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+    let start = Instant::now();
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+
+    // The mock answers `Retry-After: 1`, far longer than the 10ms backoff.
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(requests.summary().retries, 1);
+}
+
+#[test]
+fn caps_retry_after_at_max_backoff() {
+    let server = MockServer::start(&["--throttle-every", "2"]);
+    let mut requests = layer(3);
+
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+    let start = Instant::now();
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+
+    // `Retry-After: 1` is cut down to the 100ms `max_backoff`.
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(requests.summary().retries, 1);
+}
+
+#[test]
+fn stops_once_the_quota_is_used_up() {
+    let server = MockServer::start(&["--quota", "2"]);
+    let mut requests = layer(3);
+
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+    assert!(!requests.quota_exhausted());
+    requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza").unwrap();
+    assert!(requests.quota_exhausted());
+
+    let result = requests.get(&suggestions_url(&server), &[], "getsuggestions Zaragoza");
+
+    assert!(matches!(result, Err(RequestError::QuotaExhausted)));
+    let summary = requests.summary();
+    assert_eq!(summary.requests, 2, "no request is sent once the quota is exhausted");
+    assert_eq!((summary.quota_remaining, summary.quota_limit), (Some(0), Some(2)));
+    assert_eq!(summary.failures.len(), 1);
+}
@@ -0,0 +1,85 @@
+//! Replays the recorded Idealista responses in `data/fixtures/idealista` through the
+//! scraper, against a scratch database.
+
+use housevaluation::core::config::ScrapeConfig;
+use housevaluation::core::scrapers::Scraper;
+use housevaluation::core::scrapers::idealista::IdealistaScraper;
+use housevaluation::core::scrapers::replay::ResponseMode;
+use housevaluation::core::storage::Storage;
+use housevaluation::core::types::{Country, Operation, PropertyType};
+
+use std::collections::HashSet;
+use std::fs;
+
+const FIXTURES: &str = "data/fixtures/idealista";
+
+#[test]
+fn replays_the_fixtures_into_a_scratch_database() {
+    let database = std::env::temp_dir().join(format!("housevaluation-replay-{}.db", std::process::id()));
+    let _ = fs::remove_file(&database);
+    let scraper = || {
+        let config = ScrapeConfig::load(&format!("{}/scrape_config.toml", FIXTURES)).unwrap();
+        IdealistaScraper::with_response_mode(Country::Es, Operation::Sale, config, ResponseMode::Replay(FIXTURES.into()))
+    };
+    let scrape = || {
+        let mut scraper = scraper().unwrap();
+        let properties = scraper.scrape().unwrap();
+        (scraper, properties)
+    };
+
+    // SAFETY: the only test in this binary, so no other thread reads the environment.
+    unsafe { std::env::remove_var("DATABASE_PATH") };
+    assert!(scraper().is_err(), "a replay must not open the default database");
+    unsafe { std::env::set_var("DATABASE_PATH", &database) };
+
+    let (scraper, properties) = scrape();
+    let codes: HashSet<&str> = properties.iter().map(|p| p.property_code.as_str()).collect();
+    assert_eq!(properties.len(), 90);
+    assert_eq!(codes.len(), 90, "listings are deduplicated by property code");
+    assert!(properties.iter().all(|p| p.property_type == PropertyType::Homes));
+
+    let summary = scraper.summary().unwrap();
+    assert_eq!(summary.requests, 0, "a replay never reaches the network");
+    assert!(summary.failures.is_empty());
+    assert!(scraper.unfinished_searches().is_empty());
+
+    let coverage = scraper.coverage().unwrap();
+    let expected: HashSet<(String, PropertyType)> = [("Zaragoza", PropertyType::Homes), ("Burgos", PropertyType::Homes)]
+        .into_iter()
+        .map(|(search, property_type)| (search.to_string(), property_type))
+        .collect();
+    assert_eq!(coverage.complete, expected);
+    assert_eq!(coverage.searches.len(), 90, "every listing knows the search that returned it");
+
+    let storage = Storage::open_default().unwrap();
+    let upsert = storage.upsert_properties(Country::Es, Operation::Sale, &properties, &coverage).unwrap();
+    // A scratch database starts empty, whatever sits in `data/`.
+    assert_eq!((upsert.inserted, upsert.updated, upsert.delisted), (90, 0, 0));
+    assert_eq!(storage.listings(Country::Es, Operation::Sale).unwrap().len(), 90);
+
+    // Every search is checkpointed as done, so a second run fetches nothing new and
+    // returns the same listings from the checkpoint.
+    let (scraper, replayed) = scrape();
+    assert_eq!(replayed.len(), 90);
+    assert!(scraper.unfinished_searches().is_empty());
+
+    // With a one-page budget Zaragoza stops after its first page: done, so nothing is left
+    // to resume, but incomplete, so its listings aren't delisted.
+    storage.clear_scrape_checkpoint(Country::Es, Operation::Sale).unwrap();
+    let mut config = ScrapeConfig::load(&format!("{}/scrape_config.toml", FIXTURES)).unwrap();
+    config.max_pages = 1;
+    let mut scraper =
+        IdealistaScraper::with_response_mode(Country::Es, Operation::Sale, config, ResponseMode::Replay(FIXTURES.into())).unwrap();
+    let properties = scraper.scrape().unwrap();
+    assert_eq!(properties.len(), 80);
+    assert!(scraper.summary().unwrap().failures.is_empty());
+    assert!(scraper.unfinished_searches().is_empty());
+    let coverage = scraper.coverage().unwrap();
+    assert_eq!(coverage.complete, [("Burgos".to_string(), PropertyType::Homes)].into_iter().collect());
+
+    // Zaragoza listings the capped run missed stay listed, as their search wasn't complete.
+    let upsert = storage.upsert_properties(Country::Es, Operation::Sale, &properties, &coverage).unwrap();
+    assert_eq!((upsert.inserted, upsert.updated, upsert.delisted), (0, 80, 0));
+
+    let _ = fs::remove_file(&database);
+}
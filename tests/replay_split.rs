@@ -0,0 +1,64 @@
+//! Replays `data/fixtures/idealista-split`, where a one-page API limit forces the Zaragoza
+//! search to be split into price bands, against a scratch database.
+
+use housevaluation::core::config::ScrapeConfig;
+use housevaluation::core::scrapers::Scraper;
+use housevaluation::core::scrapers::idealista::IdealistaScraper;
+use housevaluation::core::scrapers::replay::ResponseMode;
+use housevaluation::core::types::{Country, Operation, Property, PropertyType};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const FIXTURES: &str = "data/fixtures/idealista-split";
+const LOWER: &str = "listhomes-sale-homes-0-EU-ES-50-04-001-297-price--250000-p1.json";
+const UPPER: &str = "listhomes-sale-homes-0-EU-ES-50-04-001-297-price-250000--p1.json";
+
+fn scrape(recording: &Path) -> (IdealistaScraper, Vec<Property>) {
+    let config = ScrapeConfig::load(&format!("{}/scrape_config.toml", FIXTURES)).unwrap();
+    let mut scraper =
+        IdealistaScraper::with_response_mode(Country::Es, Operation::Sale, config, ResponseMode::Replay(recording.into())).unwrap();
+    let properties = scraper.scrape().unwrap();
+    (scraper, properties)
+}
+
+#[test]
+fn splits_a_truncated_search_and_resumes_its_bands() {
+    let scratch = std::env::temp_dir().join(format!("housevaluation-replay-split-{}", std::process::id()));
+    let recording = scratch.join("recording");
+    let _ = fs::remove_dir_all(&scratch);
+    fs::create_dir_all(&recording).unwrap();
+    for entry in fs::read_dir(FIXTURES).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, recording.join(path.file_name().unwrap())).unwrap();
+    }
+
+    // SAFETY: the only test in this binary, so no other thread reads the environment.
+    unsafe { std::env::set_var("DATABASE_PATH", scratch.join("replay.db")) };
+
+    // The upper band isn't recorded yet: the lower one is checkpointed, the search isn't finished.
+    fs::rename(recording.join(UPPER), scratch.join(UPPER)).unwrap();
+    let (scraper, properties) = scrape(&recording);
+    assert_eq!(properties.len(), 24);
+    assert_eq!(scraper.summary().unwrap().failures.len(), 1);
+    assert_eq!(scraper.unfinished_searches(), ["Zaragoza (homes)"]);
+    assert!(scraper.coverage().unwrap().complete.is_empty());
+
+    // Resuming fetches only the upper band; the lower one comes from the checkpoint, as its
+    // recording is gone now.
+    fs::rename(scratch.join(UPPER), recording.join(UPPER)).unwrap();
+    fs::remove_file(recording.join(LOWER)).unwrap();
+    let (scraper, properties) = scrape(&recording);
+    let codes: HashSet<&str> = properties.iter().map(|p| p.property_code.as_str()).collect();
+    assert_eq!(properties.len(), 50, "the listing on the pivot is returned by both bands but kept once");
+    assert_eq!(codes.len(), 50);
+    assert!(scraper.summary().unwrap().failures.is_empty());
+    assert!(scraper.unfinished_searches().is_empty());
+
+    let coverage = scraper.coverage().unwrap();
+    assert_eq!(coverage.complete, [("Zaragoza".to_string(), PropertyType::Homes)].into_iter().collect());
+    assert_eq!(coverage.searches.len(), 50);
+
+    let _ = fs::remove_dir_all(&scratch);
+}